@@ -203,6 +203,20 @@ pub struct RaspiFramebufferInfo {
     pub screen_size: u32,
 }
 
+/// Send the request buffer at [`start`, `end`) to the property channel and
+/// wait for the response to overwrite it.
+fn send_buffer(start: usize, end: usize) {
+    // flush data cache around mailbox accesses
+    let mut mbox = MAILBOX.lock();
+    DCache::<Clean, PoC>::flush_range(start, end, SY);
+    mbox.write(
+        MailboxChannel::Property,
+        phys_to_bus(kernel_offset(start) as u32),
+    );
+    mbox.read(MailboxChannel::Property);
+    DCache::<Invalidate, PoC>::flush_range(start, end, SY);
+}
+
 /// Pack a sequence of concatenated tags into a request, and send the address
 /// to the mailbox.
 /// Returns `PropertyMailboxResult<typeof($tags)>`.
@@ -216,18 +230,7 @@ macro_rules! send_request {
         });
 
         let start = &req as *const _ as usize;
-        let end = start + req.0.buf_size as usize;
-        {
-            // flush data cache around mailbox accesses
-            let mut mbox = MAILBOX.lock();
-            DCache::<Clean, PoC>::flush_range(start, end, SY);
-            mbox.write(
-                MailboxChannel::Property,
-                phys_to_bus(kernel_offset(start) as u32),
-            );
-            mbox.read(MailboxChannel::Property);
-            DCache::<Invalidate, PoC>::flush_range(start, end, SY);
-        }
+        send_buffer(start, start + req.0.buf_size as usize);
 
         match req.0.req_resp_code {
             RPI_FIRMWARE_STATUS_SUCCESS => Ok(req.0.buf),
@@ -251,6 +254,56 @@ macro_rules! send_one_tag {
     }};
 }
 
+/// Max size in bytes of a request passed to `send_raw_request`
+pub const RAW_REQUEST_MAX: usize = 1024;
+
+/// Send a request already laid out in `buf`, the response overwrites it.
+/// `buf[0]` must be the size of `buf` in bytes, at most `RAW_REQUEST_MAX`.
+pub fn send_raw_request(buf: &mut [u32]) -> PropertyMailboxResult<()> {
+    #[repr(C, align(16))]
+    struct RawRequest([u32; RAW_REQUEST_MAX / 4]);
+
+    let len = buf.len();
+    assert!(len * 4 <= RAW_REQUEST_MAX && buf[0] as usize == len * 4);
+    let mut req = RawRequest([0; RAW_REQUEST_MAX / 4]);
+    req.0[..len].copy_from_slice(buf);
+    req.0[1] = RPI_FIRMWARE_STATUS_REQUEST as u32;
+    let start = req.0.as_ptr() as usize;
+    send_buffer(start, start + len * 4);
+    buf.copy_from_slice(&req.0[..len]);
+
+    match buf[1] {
+        code if code == RPI_FIRMWARE_STATUS_SUCCESS as u32 => Ok(()),
+        other => Err(PropertyMailboxError(other)),
+    }
+}
+
+/// Max size in bytes of the command line `get_command_line` reads
+const COMMAND_LINE_MAX: usize = 1024;
+
+/// Get the kernel command line the firmware made from `cmdline.txt`.
+pub fn get_command_line() -> PropertyMailboxResult<String> {
+    let tag = PropertyMailboxTag {
+        id: RPI_FIRMWARE_GET_COMMAND_LINE,
+        buf_size: COMMAND_LINE_MAX as u32,
+        req_resp_size: 0,
+        buf: [0u8; COMMAND_LINE_MAX],
+    };
+    let ret = send_request!(tag)?;
+    // bit 31 marks a response, the rest is its length
+    let len = (ret.req_resp_size & !(1 << 31)) as usize;
+    let buf = ret.buf;
+    let line = buf[..len.min(COMMAND_LINE_MAX)].split(|&c| c == 0).next();
+    Ok(String::from_utf8_lossy(line.unwrap_or(&[])).into_owned())
+}
+
+/// Get the SoC temperature. Returns millidegrees Celsius.
+pub fn get_temperature() -> PropertyMailboxResult<u32> {
+    // the only temperature id is 0, the SoC
+    let ret = send_one_tag!(RPI_FIRMWARE_GET_TEMPERATURE, [0, 0])?;
+    Ok(ret[1])
+}
+
 /// Allocates contiguous memory on the GPU. `size` and `align` are in bytes.
 /// Returns memory `handle`.
 pub fn mem_alloc(size: u32, align: u32, flags: u32) -> PropertyMailboxResult<u32> {
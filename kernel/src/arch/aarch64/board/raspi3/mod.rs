@@ -3,10 +3,16 @@
 use bcm2837::{addr::bus_to_phys, atags::Atags};
 
 pub mod mailbox;
+pub mod system_timer;
 pub mod timer;
+pub mod watchdog;
 
-use crate::drivers::gpu::fb::{self, ColorDepth, ColorFormat, FramebufferInfo, FramebufferResult};
+use crate::drivers::gpu::fb::{
+    self, ColorDepth, ColorFormat, Framebuffer, FramebufferInfo, FramebufferResult, FB_MAPPINGS,
+    FRAME_BUFFER,
+};
 use crate::drivers::mmc::bcm2835_sdhci;
+use core::sync::atomic::Ordering;
 
 pub const BOARD_NAME: &'static str = "Raspberry Pi 3";
 pub const PERIPHERALS_START: usize = bcm2837::addr::PERIPHERALS_START;
@@ -28,6 +34,11 @@ pub fn early_final() {
 
 /// Initialize raspi3 drivers
 pub fn init() {
+    // there is no device tree to take `bootargs` from
+    if let Ok(cmdline) = mailbox::get_command_line() {
+        info!("Kernel cmdline: {}", cmdline);
+        *crate::drivers::CMDLINE.write() = cmdline;
+    }
     if let Ok(fb_info) = probe_fb_info(0, 0, 0) {
         fb::init(fb_info);
     }
@@ -49,7 +60,68 @@ pub fn probe_memory() -> Option<(usize, usize)> {
     None
 }
 
+/// Reallocate the framebuffer with a new mode, zero keeps the current value.
+/// Refused while user mappings of the framebuffer live. The old framebuffer
+/// stays mapped until the firmware takes the new mode, and the old mode is
+/// restored if it rejects the new one.
+pub fn set_fb_mode(width: u32, height: u32, depth: u32) -> FramebufferResult {
+    let mut fb = FRAME_BUFFER.write();
+    let old = match fb.as_ref() {
+        Some(old) => old.fb_info,
+        None => Err(format!("no framebuffer to reconfigure"))?,
+    };
+    // mmaps raise the count with `FRAME_BUFFER` read locked
+    if FB_MAPPINGS.load(Ordering::SeqCst) > 0 {
+        Err(format!("framebuffer is mapped"))?;
+    }
+    let (width, height) = if width == 0 || height == 0 {
+        (old.xres, old.yres)
+    } else {
+        (width, height)
+    };
+    let depth = if depth == 0 { old.depth as u32 } else { depth };
+    match alloc_fb_info(width, height, depth) {
+        Ok(info) => replace_fb(&mut fb, &old, info),
+        Err(err) => {
+            if let Ok(info) = alloc_fb_info(old.xres, old.yres, old.depth as u32) {
+                // the firmware may have moved it
+                if info.paddr != old.paddr || info.screen_size != old.screen_size {
+                    let _ = replace_fb(&mut fb, &old, info);
+                }
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Unmap the framebuffer `old` and map `info` in its place in `fb`. Without
+/// a mapping, `fb` is left empty.
+fn replace_fb(
+    fb: &mut Option<Framebuffer>,
+    old: &FramebufferInfo,
+    info: FramebufferInfo,
+) -> FramebufferResult {
+    super::memory::iounmap(old.vaddr, old.screen_size);
+    match map_fb(info) {
+        Ok(info) => {
+            *fb = Some(Framebuffer::new(info));
+            Ok(info)
+        }
+        Err(err) => {
+            *fb = None;
+            Err(err)
+        }
+    }
+}
+
+/// Allocate a framebuffer of the mode and map it, see `alloc_fb_info`.
 fn probe_fb_info(width: u32, height: u32, depth: u32) -> FramebufferResult {
+    map_fb(alloc_fb_info(width, height, depth)?)
+}
+
+/// Allocate a framebuffer of the mode from the firmware, zero asks it for
+/// the current value. It is not mapped yet, `vaddr` is 0.
+fn alloc_fb_info(width: u32, height: u32, depth: u32) -> FramebufferResult {
     let (width, height) = if width == 0 || height == 0 {
         mailbox::framebuffer_get_physical_size()?
     } else {
@@ -75,15 +147,6 @@ fn probe_fb_info(width: u32, height: u32, depth: u32) -> FramebufferResult {
     }
 
     let paddr = bus_to_phys(info.bus_addr);
-    let vaddr = super::memory::ioremap(paddr as usize, info.screen_size as usize, "fb");
-    if vaddr == 0 {
-        Err(format!(
-            "cannot remap memory range [{:#x?}..{:#x?}]",
-            paddr,
-            paddr + info.screen_size
-        ))?;
-    }
-
     let depth = ColorDepth::try_from(info.depth)?;
     let format = match info.depth {
         16 => ColorFormat::RGB565,
@@ -100,7 +163,21 @@ fn probe_fb_info(width: u32, height: u32, depth: u32) -> FramebufferResult {
         depth: depth,
         format: format,
         paddr: paddr as usize,
-        vaddr: vaddr,
+        vaddr: 0,
         screen_size: info.screen_size as usize,
     })
 }
+
+/// Map the framebuffer `info` for the kernel, return it with its `vaddr`.
+fn map_fb(mut info: FramebufferInfo) -> FramebufferResult {
+    let vaddr = super::memory::ioremap(info.paddr, info.screen_size, "fb");
+    if vaddr == 0 {
+        Err(format!(
+            "cannot remap memory range [{:#x?}..{:#x?}]",
+            info.paddr,
+            info.paddr + info.screen_size
+        ))?;
+    }
+    info.vaddr = vaddr;
+    Ok(info)
+}
@@ -0,0 +1,26 @@
+//! BCM2837 system timer, a free-running 64-bit counter ticking at 1MHz
+//!
+//! (ref: BCM2835 ARM Peripherals, chapter 12)
+
+use super::PERIPHERALS_START;
+use crate::util::read;
+
+const SYSTEM_TIMER_BASE: usize = PERIPHERALS_START + 0x3000;
+/// System Timer Counter Lower 32 bits
+const CLO: usize = SYSTEM_TIMER_BASE + 0x04;
+/// System Timer Counter Higher 32 bits
+const CHI: usize = SYSTEM_TIMER_BASE + 0x08;
+
+/// Returns the counter value in microseconds.
+///
+/// The two halves can't be read at once, so read high, low, then high again
+/// and retry if the low half wrapped in between.
+pub fn read_counter() -> u64 {
+    loop {
+        let hi = read::<u32>(CHI);
+        let lo = read::<u32>(CLO);
+        if read::<u32>(CHI) == hi {
+            return ((hi as u64) << 32) | lo as u64;
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! BCM2837 power management watchdog, used to reset or halt the board and
+//! as a hardware watchdog
+//!
+//! Not covered by the peripherals datasheet, the sequence follows the
+//! Linux bcm2835_wdt driver.
+
+use super::PERIPHERALS_START;
+use crate::util::{read, write};
+
+const PM_BASE: usize = PERIPHERALS_START + 0x10_0000;
+/// Reset control
+const PM_RSTC: usize = PM_BASE + 0x1c;
+/// Reset status, also tells the firmware which partition to boot
+const PM_RSTS: usize = PM_BASE + 0x20;
+/// Watchdog timer, in 16us ticks
+const PM_WDOG: usize = PM_BASE + 0x24;
+
+/// Every write to a PM register must carry this password
+const PM_PASSWORD: u32 = 0x5a00_0000;
+const PM_RSTC_WRCFG_CLR: u32 = 0xffff_ffcf;
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x0000_0020;
+const PM_RSTC_RESET: u32 = 0x0000_0102;
+const PM_WDOG_TIME_SET: u32 = 0x000f_ffff;
+/// Partition 63, the firmware stays halted instead of booting it
+const PM_RSTS_RASPBERRYPI_HALT: u32 = 0x555;
+
+/// Watchdog ticks in a second
+const TICKS_PER_SEC: u32 = 1 << 16;
+/// Longest timeout the watchdog counter can hold
+pub const MAX_TIMEOUT_SECS: u32 = PM_WDOG_TIME_SET / TICKS_PER_SEC;
+
+/// Reset the board after `ticks`, unless armed again before.
+fn arm(ticks: u32) {
+    write::<u32>(PM_WDOG, PM_PASSWORD | (ticks & PM_WDOG_TIME_SET));
+    let rstc = read::<u32>(PM_RSTC) & PM_RSTC_WRCFG_CLR;
+    write::<u32>(PM_RSTC, PM_PASSWORD | rstc | PM_RSTC_WRCFG_FULL_RESET);
+}
+
+/// Start the watchdog, or restart its count if running. The board is reset
+/// unless this is called again within `secs` seconds.
+pub fn start(secs: u32) {
+    assert!(secs <= MAX_TIMEOUT_SECS);
+    arm(secs * TICKS_PER_SEC);
+}
+
+/// Stop the watchdog.
+pub fn stop() {
+    write::<u32>(PM_RSTC, PM_PASSWORD | PM_RSTC_RESET);
+}
+
+/// Arm the watchdog with a short timeout and wait for it to reset the board.
+pub fn reboot() -> ! {
+    arm(10);
+    loop {
+        aarch64::asm::wfe();
+    }
+}
+
+/// Reset into the halt partition, so the firmware does not boot again.
+pub fn halt() -> ! {
+    let rsts = read::<u32>(PM_RSTS);
+    write::<u32>(PM_RSTS, PM_PASSWORD | rsts | PM_RSTS_RASPBERRYPI_HALT);
+    reboot()
+}
@@ -1,15 +1,10 @@
 //! Input/output for aarch64.
 
-use crate::drivers::SERIAL_DRIVERS;
+use crate::drivers::serial::console_write;
 use core::fmt::{Arguments, Write};
 
 pub fn putfmt(fmt: Arguments) {
-    {
-        let mut drivers = SERIAL_DRIVERS.write();
-        if let Some(serial) = drivers.first_mut() {
-            serial.write(format!("{}", fmt).as_bytes());
-        }
-    }
+    console_write(format!("{}", fmt).as_bytes());
 
     // print to graphic
     #[cfg(feature = "consolegraphic")]
@@ -2,7 +2,9 @@
 
 use super::paging::MMIOType;
 use crate::consts::{KERNEL_OFFSET, MEMORY_OFFSET};
-use crate::memory::{init_heap, kernel_offset, Linear, MemoryAttr, MemorySet, FRAME_ALLOCATOR};
+use crate::memory::{
+    init_heap, insert_frames, kernel_offset, Linear, MemoryAttr, MemorySet, FRAME_ALLOCATOR,
+};
 use crate::sync::SpinNoIrqLock as Mutex;
 use aarch64::paging::frame::PhysFrame as Frame;
 use aarch64::regs::*;
@@ -27,7 +29,6 @@ pub fn init_other() {
 }
 
 fn init_frame_allocator() {
-    use bitmap_allocator::BitAlloc;
     use core::ops::Range;
 
     let end = super::board::probe_memory()
@@ -35,7 +36,7 @@ fn init_frame_allocator() {
         .1;
     let start = kernel_offset(_end as usize) + MEMORY_OFFSET + PAGE_SIZE;
     let mut ba = FRAME_ALLOCATOR.lock();
-    ba.insert(to_range(start, end));
+    insert_frames(&mut ba, to_range(start, end));
     info!("FrameAllocator init end");
 
     /// Transform memory area `[start, end)` to integer range for `FrameAllocator`
@@ -118,6 +119,13 @@ pub fn ioremap(paddr: usize, len: usize, name: &'static str) -> usize {
     0
 }
 
+/// Remove a mapping made by `ioremap`.
+pub fn iounmap(vaddr: usize, len: usize) {
+    if let Some(ms) = KERNEL_MEMORY_SET.lock().as_mut() {
+        ms.pop_with_split(vaddr, vaddr + len);
+    }
+}
+
 extern "C" {
     fn stext();
     fn etext();
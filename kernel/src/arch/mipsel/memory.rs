@@ -1,6 +1,6 @@
 use crate::arch::paging::*;
 use crate::consts::{KERNEL_OFFSET, MEMORY_END, MEMORY_OFFSET};
-use crate::memory::{init_heap, FRAME_ALLOCATOR};
+use crate::memory::{init_heap, insert_frames, FRAME_ALLOCATOR};
 use mips::registers::cp0;
 use rcore_memory::PAGE_SIZE;
 
@@ -23,7 +23,6 @@ pub fn init_other() {
 }
 
 fn init_frame_allocator() {
-    use bitmap_allocator::BitAlloc;
     use core::ops::Range;
 
     let mut ba = FRAME_ALLOCATOR.lock();
@@ -31,7 +30,7 @@ fn init_frame_allocator() {
         (end as usize) - KERNEL_OFFSET + MEMORY_OFFSET + PAGE_SIZE,
         MEMORY_END,
     );
-    ba.insert(range);
+    insert_frames(&mut ba, range);
 
     info!("frame allocator: init end");
 
@@ -1,3 +1,4 @@
+use crate::drivers::serial::console_port;
 use crate::drivers::SerialDriver;
 use crate::drivers::SERIAL_DRIVERS;
 use alloc::sync::Arc;
@@ -22,7 +23,7 @@ impl core::fmt::Write for HeaplessSBIWrite {
 pub fn putfmt(fmt: Arguments) {
     // output to serial
     let mut drivers = SERIAL_DRIVERS.write();
-    if let Some(serial) = drivers.first_mut() {
+    if let Some(serial) = drivers.get_mut(console_port()) {
         HeaplessWrite(&serial).write_fmt(fmt).unwrap();
     } else {
         // might miss some early messages.
@@ -1,5 +1,5 @@
 use crate::consts::{KERNEL_OFFSET, MEMORY_END, MEMORY_OFFSET};
-use crate::memory::{init_heap, MemorySet, FRAME_ALLOCATOR};
+use crate::memory::{init_heap, insert_frames, MemorySet, FRAME_ALLOCATOR};
 use core::mem;
 use log::*;
 use rcore_memory::PAGE_SIZE;
@@ -27,7 +27,6 @@ pub fn init_other() {
 }
 
 fn init_frame_allocator() {
-    use bitmap_allocator::BitAlloc;
     use core::ops::Range;
 
     let mut ba = FRAME_ALLOCATOR.lock();
@@ -35,7 +34,7 @@ fn init_frame_allocator() {
         (end as usize) - KERNEL_OFFSET + MEMORY_OFFSET + PAGE_SIZE,
         MEMORY_END,
     );
-    ba.insert(range);
+    insert_frames(&mut ba, range);
 
     info!("frame allocator: init end");
 
@@ -1,14 +1,10 @@
-use crate::drivers::SERIAL_DRIVERS;
+use crate::drivers::serial::console_write;
 use core::fmt::{Arguments, Write};
 
 pub fn putfmt(fmt: Arguments) {
     // output to serial
     #[cfg(not(feature = "board_pc"))]
-    {
-        let mut drivers = SERIAL_DRIVERS.write();
-        let serial = drivers.first_mut().unwrap();
-        serial.write(format!("{}", fmt).as_bytes());
-    }
+    console_write(format!("{}", fmt).as_bytes());
 
     // print to graphic
     #[cfg(feature = "consolegraphic")]
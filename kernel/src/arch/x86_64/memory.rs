@@ -1,6 +1,5 @@
 use super::paging::PageTableImpl;
-use crate::memory::FRAME_ALLOCATOR;
-use bitmap_allocator::BitAlloc;
+use crate::memory::{insert_frames, FRAME_ALLOCATOR};
 use rboot::{BootInfo, MemoryType};
 use rcore_memory::paging::*;
 use rcore_memory::PAGE_SIZE;
@@ -22,7 +21,7 @@ fn init_frame_allocator(boot_info: &BootInfo) {
         if region.ty == MemoryType::CONVENTIONAL {
             let start_frame = region.phys_start as usize / PAGE_SIZE;
             let end_frame = start_frame + region.page_count as usize;
-            ba.insert(start_frame..end_frame);
+            insert_frames(&mut ba, start_frame..end_frame);
         }
     }
 }
@@ -2,6 +2,7 @@
 
 use alloc::string::String;
 use core::fmt;
+use core::sync::atomic::AtomicUsize;
 use log::*;
 use spin::RwLock;
 
@@ -150,7 +151,7 @@ pub struct Framebuffer {
 }
 
 impl Framebuffer {
-    fn new(info: FramebufferInfo) -> Framebuffer {
+    pub fn new(info: FramebufferInfo) -> Framebuffer {
         Framebuffer {
             buf: ColorBuffer::new(info.depth, info.vaddr, info.screen_size),
             fb_info: info,
@@ -311,6 +312,11 @@ impl ColorEncode for Rgb888 {
 
 pub static FRAME_BUFFER: RwLock<Option<Framebuffer>> = RwLock::new(None);
 
+/// Number of live user mappings of the framebuffer. Its mode is not changed
+/// while there are any, they would be left pointing at the old one.
+/// Only raised with `FRAME_BUFFER` read locked
+pub static FB_MAPPINGS: AtomicUsize = AtomicUsize::new(0);
+
 /// Initialize framebuffer
 ///
 /// Called in arch mod if the board have a framebuffer
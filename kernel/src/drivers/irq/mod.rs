@@ -1,14 +1,37 @@
-use super::Driver;
+use super::{DeviceType, Driver};
 use crate::arch::interrupt::enable_irq;
 use alloc::collections::btree_map::Entry;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(feature = "board_raspi3")]
 pub mod bcm2837;
 pub mod plic;
 
+/// Interrupts handled since boot, indexed by the `DeviceType` of the
+/// driver which handled them
+static HANDLED: [AtomicUsize; 7] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Number of interrupts handled by drivers of `type_` since boot. An
+/// interrupt passed on by an interrupt controller counts for both.
+pub fn handled_count(type_: DeviceType) -> usize {
+    HANDLED[type_ as usize].load(Ordering::Relaxed)
+}
+
+fn count_handled(driver: &Arc<dyn Driver>) {
+    HANDLED[driver.device_type() as usize].fetch_add(1, Ordering::Relaxed);
+}
+
 // Irq manager
 pub struct IrqManager {
     // is root manager?
@@ -73,6 +96,7 @@ impl IrqManager {
             if let Some(e) = self.mapping.get(&irq) {
                 for dri in e.iter() {
                     if dri.try_handle_interrupt(Some(irq)) {
+                        count_handled(dri);
                         return true;
                     }
                 }
@@ -81,6 +105,7 @@ impl IrqManager {
 
         for dri in self.all.iter() {
             if dri.try_handle_interrupt(irq_opt) {
+                count_handled(dri);
                 return true;
             }
         }
@@ -3,14 +3,25 @@ use super::super::irq::IntcDriver;
 use super::super::DRIVERS;
 use super::super::IRQ_MANAGER;
 use super::{super::SERIAL_DRIVERS, SerialDriver};
+use crate::arch::board::PERIPHERALS_START;
 use crate::drivers::irq::bcm2837::BCM2837_INTC;
 use crate::drivers::{DeviceType, Driver};
 use crate::sync::SpinNoIrqLock as Mutex;
+use crate::util::{read, write};
 use alloc::string::String;
 use alloc::sync::Arc;
 use bcm2837::interrupt::Interrupt;
 use bcm2837::mini_uart::{MiniUart, MiniUartInterruptId};
 
+/// AUX_MU_LSR_REG, line status of the mini UART
+const AUX_MU_LSR: usize = PERIPHERALS_START + 0x21_5054;
+/// the transmit FIFO is empty and the last bit has been sent
+const AUX_MU_LSR_TX_IDLE: u32 = 1 << 6;
+/// AUX_MU_BAUD_REG, the rate is `CORE_CLOCK / (8 * (value + 1))`
+const AUX_MU_BAUD: usize = PERIPHERALS_START + 0x21_5068;
+/// VPU core clock the mini UART runs from, in Hz
+const CORE_CLOCK: u32 = 250_000_000;
+
 struct Bcm2837Serial {
     mu: Mutex<MiniUart>,
 }
@@ -49,6 +60,32 @@ impl SerialDriver for Bcm2837Serial {
             mu.write_byte(*byte);
         }
     }
+
+    fn try_read(&self) -> Option<u8> {
+        let mu = self.mu.lock();
+        if mu.has_byte() {
+            Some(mu.read_byte())
+        } else {
+            None
+        }
+    }
+
+    fn tx_idle(&self) -> bool {
+        read::<u32>(AUX_MU_LSR) & AUX_MU_LSR_TX_IDLE != 0
+    }
+
+    fn set_baud(&self, baud: u32) -> Option<u32> {
+        if baud == 0 {
+            return None;
+        }
+        let value = (CORE_CLOCK / 8 + baud / 2) / baud;
+        if value == 0 || value > 0x1_0000 {
+            return None;
+        }
+        let _mu = self.mu.lock();
+        write::<u32>(AUX_MU_BAUD, value - 1);
+        Some(CORE_CLOCK / 8 / value)
+    }
 }
 
 pub fn driver_init() {
@@ -1,6 +1,7 @@
 //! COM ports in x86
 use super::super::DRIVERS;
 use super::super::IRQ_MANAGER;
+use super::uart16550::{divisor, DEFAULT_CLOCK};
 use super::{super::SERIAL_DRIVERS, SerialDriver};
 use crate::{
     drivers::{DeviceType, Driver},
@@ -9,10 +10,21 @@ use crate::{
 use alloc::string::String;
 use alloc::sync::Arc;
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
 
 pub const COM2: usize = 3;
 pub const COM1: usize = 4;
 
+/// Line status register, and its bit telling the transmitter is empty
+const LINE_STATUS: u16 = 5;
+const LINE_STATUS_TEMT: u8 = 0x40;
+/// Line status bit telling a received byte is ready
+const LINE_STATUS_DR: u8 = 0x01;
+/// Line control register, and its bit mapping the divisor latch at the
+/// first two registers
+const LINE_CONTROL: u16 = 3;
+const LINE_CONTROL_DLAB: u8 = 0x80;
+
 struct COM {
     port: Mutex<SerialPort>,
     base: u16,
@@ -28,8 +40,9 @@ impl COM {
 
 impl Driver for COM {
     fn try_handle_interrupt(&self, irq: Option<usize>) -> bool {
-        let c = self.read();
-        crate::trap::serial(c);
+        while let Some(c) = self.try_read() {
+            crate::trap::serial(c);
+        }
         true
     }
 
@@ -47,12 +60,41 @@ impl SerialDriver for COM {
         self.port.lock().receive()
     }
 
+    fn try_read(&self) -> Option<u8> {
+        // hold the port so no write starts meanwhile
+        let _port = self.port.lock();
+        let status = unsafe { Port::<u8>::new(self.base + LINE_STATUS).read() };
+        if status & LINE_STATUS_DR == 0 {
+            return None;
+        }
+        Some(unsafe { Port::<u8>::new(self.base).read() })
+    }
+
     fn write(&self, data: &[u8]) {
         let mut port = self.port.lock();
         for byte in data {
             port.send(*byte);
         }
     }
+
+    fn tx_idle(&self) -> bool {
+        let status = unsafe { Port::<u8>::new(self.base + LINE_STATUS).read() };
+        status & LINE_STATUS_TEMT != 0
+    }
+
+    fn set_baud(&self, baud: u32) -> Option<u32> {
+        let (divisor, actual) = divisor(DEFAULT_CLOCK, baud)?;
+        let _port = self.port.lock();
+        let mut lcr = Port::<u8>::new(self.base + LINE_CONTROL);
+        unsafe {
+            let old = lcr.read();
+            lcr.write(old | LINE_CONTROL_DLAB);
+            Port::<u8>::new(self.base).write(divisor as u8);
+            Port::<u8>::new(self.base + 1).write((divisor >> 8) as u8);
+            lcr.write(old & !LINE_CONTROL_DLAB);
+        }
+        Some(actual)
+    }
 }
 
 pub fn init() {
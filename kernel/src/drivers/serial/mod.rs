@@ -1,7 +1,10 @@
-use super::Driver;
-use super::SERIAL_DRIVERS;
+use super::irq::handled_count;
+use super::{DeviceType, Driver, SERIAL_DRIVERS};
+use crate::sync::SpinNoIrqLock;
+use alloc::string::String;
 use alloc::sync::Arc;
 use core::fmt::{Result, Write};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 #[cfg(feature = "board_raspi3")]
 pub mod bcm2837;
@@ -20,12 +23,242 @@ pub trait SerialDriver: Driver {
     // write bytes to tty
     fn write(&self, data: &[u8]);
 
-    // get if it is ready. as a hint.
+    // read one byte if one is ready, without blocking.
+    // None if none is, or the port can not tell without blocking
     fn try_read(&self) -> Option<u8> {
-        Some(self.read())
+        None
+    }
+
+    // whether written bytes have left the port, including the last bit.
+    // ports which can not tell say they have
+    fn tx_idle(&self) -> bool {
+        true
+    }
+
+    // set the baud rate to the closest one the port can do, return it.
+    // None if the port has no rate or can not get close
+    fn set_baud(&self, _baud: u32) -> Option<u32> {
+        None
     }
 }
 use crate::sync::Condvar;
 lazy_static! {
     pub static ref SERIAL_ACTIVITY: Condvar = Condvar::new();
 }
+
+/// Poll serial ports for input on every timer tick instead of waiting for
+/// the receive interrupt.
+///
+/// Input is normally pushed to the tty from the RX interrupt handler, so
+/// readers sleep until a byte arrives. Some emulators (e.g. QEMU v3.0 without
+/// M-mode external interrupts) never deliver that interrupt; enable this
+/// (or build with the `serial_polling` feature) as a fallback there.
+/// Only ports with a non-blocking `try_read` can be polled.
+///
+/// Unless set by hand, polling turns on by itself when input is found
+/// waiting at a port while no receive interrupt ever fired, and off again
+/// once one does. An idle port never turns it on.
+pub static SERIAL_POLLING: AtomicBool = AtomicBool::new(cfg!(feature = "serial_polling"));
+
+/// `SERIAL_POLLING` was set by hand or the feature, it is not detected
+static POLLING_FORCED: AtomicBool = AtomicBool::new(cfg!(feature = "serial_polling"));
+
+/// Timer ticks serial ports were polled on
+static POLL_ROUNDS: AtomicUsize = AtomicUsize::new(0);
+/// Input bytes taken by polling
+static POLLED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Index in `SERIAL_DRIVERS` of the port console output goes to
+static CONSOLE_PORT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn console_port() -> usize {
+    CONSOLE_PORT.load(Ordering::Relaxed)
+}
+
+/// Send console output to serial port `index` from now on.
+/// Return false if there is no such port.
+pub fn set_console_port(index: usize) -> bool {
+    if index >= SERIAL_DRIVERS.read().len() {
+        return false;
+    }
+    CONSOLE_PORT.store(index, Ordering::Relaxed);
+    true
+}
+
+/// Size of the buffer keeping output printed before the console port exists
+const EARLY_OUTPUT_MAX: usize = 4096;
+
+/// Output printed before the console port exists, without using the heap
+struct EarlyOutput {
+    buf: [u8; EARLY_OUTPUT_MAX],
+    len: usize,
+    /// bytes which did not fit
+    dropped: usize,
+}
+
+static EARLY_OUTPUT: SpinNoIrqLock<EarlyOutput> = SpinNoIrqLock::new(EarlyOutput {
+    buf: [0; EARLY_OUTPUT_MAX],
+    len: 0,
+    dropped: 0,
+});
+/// `EARLY_OUTPUT` has output not written to the console port yet
+static EARLY_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Write `data` to the console port.
+///
+/// Before the console port exists, output is kept in a buffer of
+/// `EARLY_OUTPUT_MAX` bytes and written first once it does, so early
+/// messages are not lost. Output beyond the buffer is dropped.
+pub fn console_write(data: &[u8]) {
+    let drivers = SERIAL_DRIVERS.read();
+    match drivers.get(console_port()) {
+        Some(serial) => {
+            if EARLY_PENDING.load(Ordering::Relaxed) {
+                let mut early = EARLY_OUTPUT.lock();
+                serial.write(&early.buf[..early.len]);
+                if early.dropped > 0 {
+                    // called while printing, so it can't use log macros
+                    let msg = format!("\n[{} bytes of early output dropped]\n", early.dropped);
+                    serial.write(msg.as_bytes());
+                }
+                early.len = 0;
+                early.dropped = 0;
+                EARLY_PENDING.store(false, Ordering::Relaxed);
+            }
+            serial.write(data);
+        }
+        None => {
+            let mut early = EARLY_OUTPUT.lock();
+            let start = early.len;
+            let len = data.len().min(EARLY_OUTPUT_MAX - start);
+            early.buf[start..start + len].copy_from_slice(&data[..len]);
+            early.len += len;
+            early.dropped += data.len() - len;
+            EARLY_PENDING.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Whether output written to the console port has been transmitted.
+/// Callers wait for it by sleeping between checks, never by spinning.
+pub fn console_drained() -> bool {
+    match SERIAL_DRIVERS.read().get(console_port()) {
+        Some(serial) => serial.tx_idle(),
+        None => true,
+    }
+}
+
+/// Set the baud rate of the console port, return the rate it got, see
+/// `SerialDriver::set_baud`. Bytes still in the FIFO would go out garbled
+/// at the new rate, drain it first, see `console_drained`.
+pub fn console_set_baud(baud: u32) -> Option<u32> {
+    let drivers = SERIAL_DRIVERS.read();
+    let serial = drivers.get(console_port())?;
+    serial.set_baud(baud)
+}
+
+/// Turn polling on or off for good, ending the detection.
+pub fn set_polling(enable: bool) {
+    POLLING_FORCED.store(true, Ordering::Relaxed);
+    SERIAL_POLLING.store(enable, Ordering::Relaxed);
+}
+
+/// Drain pending input of all serial ports into the tty.
+/// Called from the timer interrupt on every tick. Unless polling is
+/// enabled it only looks for input missed by the interrupt, see
+/// `detect_polling`.
+pub fn poll_input() {
+    detect_polling();
+    if !SERIAL_POLLING.load(Ordering::Relaxed) {
+        return;
+    }
+    POLL_ROUNDS.fetch_add(1, Ordering::Relaxed);
+    for driver in SERIAL_DRIVERS.read().iter() {
+        while let Some(c) = driver.try_read() {
+            POLLED_BYTES.fetch_add(1, Ordering::Relaxed);
+            crate::trap::serial(c);
+        }
+    }
+}
+
+/// Fall back to polling if input is waiting at a port while no receive
+/// interrupt ever fired, go back to the interrupt once one fires.
+///
+/// Until the first interrupt, ports are looked at once a tick. Only ports
+/// with a non-blocking `try_read` can report waiting input, so only those
+/// start polling.
+fn detect_polling() {
+    if POLLING_FORCED.load(Ordering::Relaxed) {
+        return;
+    }
+    let interrupts = handled_count(DeviceType::Serial);
+    let polling = SERIAL_POLLING.load(Ordering::Relaxed);
+    let mut input_waiting = false;
+    if !polling && interrupts == 0 {
+        for driver in SERIAL_DRIVERS.read().iter() {
+            while let Some(c) = driver.try_read() {
+                input_waiting = true;
+                POLLED_BYTES.fetch_add(1, Ordering::Relaxed);
+                crate::trap::serial(c);
+            }
+        }
+    }
+    let poll = should_poll(polling, interrupts, input_waiting);
+    if poll == polling {
+        return;
+    }
+    SERIAL_POLLING.store(poll, Ordering::Relaxed);
+    if poll {
+        warn!("serial: input arrived without a receive interrupt, polling input");
+    } else {
+        info!("serial: receive interrupt fired, polling stopped");
+    }
+}
+
+/// Whether input is to be polled, given whether it is now, the receive
+/// interrupts handled so far and whether input was found waiting.
+fn should_poll(polling: bool, interrupts: usize, input_waiting: bool) -> bool {
+    interrupts == 0 && (polling || input_waiting)
+}
+
+/// Lines of /proc/interrupts telling how serial input is serviced: the
+/// mode, and the ticks polled and bytes taken by polling. Receive
+/// interrupts are on the `uart` line.
+pub fn polling_report() -> String {
+    let polling = SERIAL_POLLING.load(Ordering::Relaxed);
+    let mode = match (polling, POLLING_FORCED.load(Ordering::Relaxed)) {
+        (true, true) => "polling (set)",
+        (true, false) => "polling (no interrupt)",
+        (false, true) => "interrupt (set)",
+        (false, false) => "interrupt",
+    };
+    format!(
+        "uart_mode: {}\nuart_polls: {}\nuart_polled: {}\n",
+        mode,
+        POLL_ROUNDS.load(Ordering::Relaxed),
+        POLLED_BYTES.load(Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::should_poll;
+
+    #[test]
+    fn idle_port_keeps_interrupt() {
+        assert!(!should_poll(false, 0, false));
+    }
+
+    #[test]
+    fn input_without_interrupt_starts_polling() {
+        assert!(should_poll(false, 0, true));
+        // and it goes on while no interrupt fires
+        assert!(should_poll(true, 0, false));
+    }
+
+    #[test]
+    fn interrupt_stops_polling() {
+        assert!(!should_poll(true, 1, false));
+        assert!(!should_poll(false, 1, true));
+    }
+}
@@ -17,6 +17,8 @@ use device_tree::Node;
 pub struct SerialPort {
     base: usize,
     multiplier: usize,
+    /// input clock in Hz
+    clock: u32,
 }
 
 impl Driver for SerialPort {
@@ -40,10 +42,11 @@ impl Driver for SerialPort {
 }
 
 impl SerialPort {
-    fn new(base: usize, shift: usize) -> SerialPort {
+    fn new(base: usize, shift: usize, clock: u32) -> SerialPort {
         let mut res = SerialPort {
             base: 0,
             multiplier: 1 << shift,
+            clock,
         };
         res.init(base);
         res
@@ -117,6 +120,20 @@ impl SerialDriver for SerialPort {
     fn try_read(&self) -> Option<u8> {
         self.getchar_option()
     }
+
+    fn set_baud(&self, baud: u32) -> Option<u32> {
+        let (divisor, actual) = divisor(self.clock, baud)?;
+        let lcr = read::<u8>(self.base + COM_LCR * self.multiplier);
+        write(self.base + COM_LCR * self.multiplier, lcr | COM_LCR_DLAB);
+        write(self.base + COM_DLL * self.multiplier, divisor as u8);
+        write(self.base + COM_DLM * self.multiplier, (divisor >> 8) as u8);
+        write(self.base + COM_LCR * self.multiplier, lcr & !COM_LCR_DLAB);
+        Some(actual)
+    }
+
+    fn tx_idle(&self) -> bool {
+        read::<u8>(self.base + COM_LSR * self.multiplier) & COM_LSR_TSRE != 0
+    }
 }
 
 const COM_RX: usize = 0; // In:  Receive buffer (DLAB=0)
@@ -139,12 +156,31 @@ const COM_LSR_DATA: u8 = 0x01; // Data available
 const COM_LSR_TXRDY: u8 = 0x20; // Transmit buffer avail
 const COM_LSR_TSRE: u8 = 0x40; // Transmitter off
 
+/// Input clock of a 16550 if the device tree does not tell
+pub const DEFAULT_CLOCK: u32 = 1_843_200;
+
+/// Divisor latch value giving the rate closest to `baud` from an input
+/// clock of `clock` Hz, with the rate it gives.
+pub fn divisor(clock: u32, baud: u32) -> Option<(u16, u32)> {
+    if baud == 0 {
+        return None;
+    }
+    // the UART samples each bit 16 times
+    let base = clock / 16;
+    let divisor = (base + baud / 2) / baud;
+    if divisor == 0 || divisor > 0xffff {
+        return None;
+    }
+    Some((divisor as u16, base / divisor))
+}
+
 pub fn init_dt(dt: &Node) {
     let addr = dt.prop_usize("reg").unwrap();
     let shift = dt.prop_u32("reg-shift").unwrap_or(0) as usize;
+    let clock = dt.prop_u32("clock-frequency").unwrap_or(DEFAULT_CLOCK);
     let base = phys_to_virt(addr);
     info!("Init uart16550 at {:#x}", base);
-    let com = Arc::new(SerialPort::new(base, shift));
+    let com = Arc::new(SerialPort::new(base, shift, clock));
     let mut found = false;
     let irq_opt = dt.prop_u32("interrupts").ok().map(|irq| irq as usize);
     DRIVERS.write().push(com.clone());
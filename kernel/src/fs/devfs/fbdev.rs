@@ -1,15 +1,70 @@
 //! Implement INode for framebuffer
 
-use crate::drivers::gpu::fb::{ColorFormat, FramebufferInfo, FRAME_BUFFER};
+use crate::drivers::gpu::fb::{ColorFormat, FramebufferInfo, FB_MAPPINGS, FRAME_BUFFER};
+use crate::fs::ioctl::{FBIOGET_FSCREENINFO, FBIOGET_VSCREENINFO, FBIOPUT_VSCREENINFO};
+use crate::process::current_thread;
 use crate::syscall::MmapProt;
+use alloc::boxed::Box;
 use core::any::Any;
+use core::sync::atomic::Ordering;
 
 use rcore_fs::vfs::*;
-use rcore_memory::memory_set::handler::Linear;
+use rcore_memory::memory_set::handler::{Linear, MemoryHandler};
+use rcore_memory::memory_set::MemoryAttr;
+use rcore_memory::paging::PageTable;
+use rcore_memory::VirtAddr;
 
 #[derive(Default)]
 pub struct Fbdev;
 
+/// A user mapping of the framebuffer, counted in `FB_MAPPINGS` while it
+/// lives. Areas are cloned on fork and split by partial unmaps, each copy
+/// counts.
+#[derive(Debug)]
+struct FbMapping(Linear);
+
+impl FbMapping {
+    /// Only made with `FRAME_BUFFER` read locked, or cloned from a live one.
+    fn new(linear: Linear) -> Self {
+        FB_MAPPINGS.fetch_add(1, Ordering::SeqCst);
+        FbMapping(linear)
+    }
+}
+
+impl Drop for FbMapping {
+    fn drop(&mut self) {
+        FB_MAPPINGS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MemoryHandler for FbMapping {
+    fn box_clone(&self) -> Box<dyn MemoryHandler> {
+        Box::new(FbMapping::new(self.0.clone()))
+    }
+
+    fn map(&self, pt: &mut dyn PageTable, addr: VirtAddr, attr: &MemoryAttr) {
+        self.0.map(pt, addr, attr);
+    }
+
+    fn unmap(&self, pt: &mut dyn PageTable, addr: VirtAddr) {
+        self.0.unmap(pt, addr);
+    }
+
+    fn clone_map(
+        &self,
+        pt: &mut dyn PageTable,
+        src_pt: &mut dyn PageTable,
+        addr: VirtAddr,
+        attr: &MemoryAttr,
+    ) {
+        self.0.clone_map(pt, src_pt, addr, attr);
+    }
+
+    fn handle_page_fault(&self, pt: &mut dyn PageTable, addr: VirtAddr) -> bool {
+        self.0.handle_page_fault(pt, addr)
+    }
+}
+
 impl INode for Fbdev {
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         info!(
@@ -66,11 +121,10 @@ impl INode for Fbdev {
             rdev: make_rdev(29, 0),
         })
     }
+    /// The argument is checked to cover the structure of the request by
+    /// sys_ioctl.
     fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
-        const FBIOGET_VSCREENINFO: u32 = 0x4600;
-        const FBIOGET_FSCREENINFO: u32 = 0x4602;
-
-        match cmd {
+        match cmd as usize {
             FBIOGET_FSCREENINFO => {
                 let fb_fix_info = unsafe { &mut *(data as *mut FbFixScreeninfo) };
                 if let Some(fb) = FRAME_BUFFER.read().as_ref() {
@@ -85,6 +139,16 @@ impl INode for Fbdev {
                 }
                 Ok(0)
             }
+            FBIOPUT_VSCREENINFO => {
+                let fb_var_info = unsafe { &mut *(data as *mut FbVarScreeninfo) };
+                let info = set_mode(
+                    fb_var_info.xres,
+                    fb_var_info.yres,
+                    fb_var_info.bits_per_pixel,
+                )?;
+                fb_var_info.fill_from(&info);
+                Ok(0)
+            }
             _ => {
                 warn!("use never support ioctl !");
                 Err(FsError::NotSupported)
@@ -100,16 +164,18 @@ impl INode for Fbdev {
             if area.offset + area.end_vaddr - area.start_vaddr > fb.framebuffer_size() {
                 return Err(FsError::NoDeviceSpace);
             }
-            /*
-            let thread = unsafe { crate::process::current_thread() };
+            // counted before `FRAME_BUFFER` is unlocked, so no mode change
+            // slips in between
+            let linear =
+                Linear::new(fb.paddr() as isize + area.offset as isize - area.start_vaddr as isize);
+            let thread = current_thread().ok_or(FsError::NoDevice)?;
             thread.vm.lock().push(
                 area.start_vaddr,
                 area.end_vaddr,
                 attr,
-                Linear::new((fb.paddr() + area.offset - area.start_vaddr) as isize),
-                "mmap_file",
+                FbMapping::new(linear),
+                "mmap_fb",
             );
-            */
             Ok(())
         } else {
             Err(FsError::NoDevice)
@@ -120,6 +186,26 @@ impl INode for Fbdev {
     }
 }
 
+/// Change the resolution and depth of the framebuffer, if the board can.
+#[cfg(feature = "board_raspi3")]
+fn set_mode(xres: u32, yres: u32, depth: u32) -> Result<FramebufferInfo> {
+    if FRAME_BUFFER.read().is_none() {
+        return Err(FsError::NoDevice);
+    }
+    crate::arch::board::set_fb_mode(xres, yres, depth).map_err(|err| {
+        warn!(
+            "fbdev: cannot set mode {}x{}x{}: {}",
+            xres, yres, depth, err
+        );
+        FsError::InvalidParam
+    })
+}
+
+#[cfg(not(feature = "board_raspi3"))]
+fn set_mode(_xres: u32, _yres: u32, _depth: u32) -> Result<FramebufferInfo> {
+    Err(FsError::NotSupported)
+}
+
 #[repr(u32)]
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone)]
@@ -163,7 +249,7 @@ const FB_ACCEL_NONE: u32 = 0;
 
 #[repr(C)]
 #[derive(Debug)]
-struct FbFixScreeninfo {
+pub struct FbFixScreeninfo {
     /// identification string eg "TT Builtin"
     id: [u8; 16],
     /// Start of frame buffer mem (physical address)
@@ -198,7 +284,7 @@ struct FbFixScreeninfo {
 
 #[repr(C)]
 #[derive(Debug)]
-struct FbVarScreeninfo {
+pub struct FbVarScreeninfo {
     /// visible resolution x
     xres: u32,
     /// visible resolution y
@@ -286,7 +372,8 @@ impl FbVarScreeninfo {
             ColorFormat::RGB565 => (5, 6, 5, 0, 11, 5, 0, 0),
             ColorFormat::RGBA8888 => (8, 8, 8, 8, 16, 8, 0, 24),
             ColorFormat::BGRA8888 => (8, 8, 8, 8, 0, 8, 16, 24),
-            ColorFormat::VgaPalette => unimplemented!(),
+            // pixels are palette indexes, all channels cover the index
+            ColorFormat::VgaPalette => (8, 8, 8, 0, 0, 0, 0, 0),
         };
         self.blue = FbBitfield {
             offset: bo,
@@ -0,0 +1,100 @@
+//! Implement INode for the VideoCore mailbox property interface
+
+use crate::arch::board::mailbox::{send_raw_request, RAW_REQUEST_MAX};
+use crate::fs::ioctl::MBOX_PROPERTY;
+use crate::process::current_thread;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use rcore_fs::vfs::*;
+
+/// Pass property requests from userspace to the firmware, like Linux vcio
+///
+/// The `MBOX_PROPERTY` ioctl takes a pointer to a buffer of `u32` words:
+///
+/// - word 0: size of the whole buffer in bytes, at most 1024
+/// - word 1: 0 in the request, the response code in the response,
+///   0x80000000 for success and 0x80000001 for error
+/// - then each tag: its id, the size of its value buffer in bytes, 0 in the
+///   request, the value buffer padded to 4 bytes
+/// - last word: 0, the end tag
+///
+/// The response overwrites the buffer. For each tag the firmware sets bit 31
+/// of the third word and puts the response value length in the low bits.
+/// (ref: https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface)
+#[derive(Default)]
+pub struct MailboxINode;
+
+impl INode for MailboxINode {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            MBOX_PROPERTY => {
+                // sys_ioctl checked the header and the end tag, the memory
+                // set is only locked to check the rest, the request is
+                // copied in, sent, and the response copied out
+                let size = unsafe { *(data as *const u32) } as usize;
+                // the header and the end tag at least
+                if size < 12 || size > RAW_REQUEST_MAX || size % 4 != 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                let thread = current_thread().unwrap();
+                let user_buf = unsafe {
+                    thread
+                        .vm
+                        .lock()
+                        .check_write_array(data as *mut u32, size / 4)
+                }
+                .map_err(|_| FsError::IOCTLError)?;
+                let mut buf = Vec::from(&user_buf[..]);
+                // userspace may have changed it since it was checked
+                buf[0] = size as u32;
+                send_raw_request(&mut buf).map_err(|err| {
+                    warn!("mailbox: property request failed: {:x?}", err);
+                    FsError::DeviceError
+                })?;
+                user_buf.copy_from_slice(&buf);
+                Ok(0)
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 5,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o600,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(100, 0),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,135 @@
+//! Implement INode for physical memory and peripheral registers
+//!
+//! The file offset is the physical address. Only built with the `dev_mem`
+//! feature: any program opening it can read and write all of memory.
+
+use crate::arch::board::{PERIPHERALS_END, PERIPHERALS_START};
+use crate::fs::ioctl::{MEMGWIDTH, MEMSWIDTH};
+use crate::memory::phys_to_virt;
+use crate::util::{read, write};
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use rcore_fs::vfs::*;
+
+/// Read and write physical memory in accesses of a selectable width, so
+/// registers only taking word accesses are not broken up into bytes
+pub struct MemINode {
+    /// bytes per access, 1, 2 or 4
+    width: AtomicUsize,
+}
+
+impl Default for MemINode {
+    fn default() -> Self {
+        MemINode {
+            width: AtomicUsize::new(1),
+        }
+    }
+}
+
+impl MemINode {
+    /// Kernel address of `len` bytes at physical address `paddr`, checking
+    /// they are mapped and aligned to the access width.
+    fn vaddr(&self, paddr: usize, len: usize, width: usize) -> Result<usize> {
+        if paddr % width != 0 || len % width != 0 {
+            return Err(FsError::InvalidParam);
+        }
+        let end = paddr.checked_add(len).ok_or(FsError::InvalidParam)?;
+        if end > PERIPHERALS_END {
+            return Err(FsError::InvalidParam);
+        }
+        if paddr >= PERIPHERALS_START {
+            // peripherals are mapped at their physical address
+            Ok(paddr)
+        } else if end <= PERIPHERALS_START {
+            Ok(phys_to_virt(paddr))
+        } else {
+            Err(FsError::InvalidParam)
+        }
+    }
+}
+
+impl INode for MemINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let width = self.width.load(Ordering::Relaxed);
+        let vaddr = self.vaddr(offset, buf.len(), width)?;
+        for (i, chunk) in buf.chunks_mut(width).enumerate() {
+            let addr = vaddr + i * width;
+            match width {
+                1 => chunk[0] = read::<u8>(addr),
+                2 => chunk.copy_from_slice(&read::<u16>(addr).to_ne_bytes()),
+                _ => chunk.copy_from_slice(&read::<u32>(addr).to_ne_bytes()),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let width = self.width.load(Ordering::Relaxed);
+        let vaddr = self.vaddr(offset, buf.len(), width)?;
+        for (i, chunk) in buf.chunks(width).enumerate() {
+            let addr = vaddr + i * width;
+            match width {
+                1 => write::<u8>(addr, chunk[0]),
+                2 => write::<u16>(addr, u16::from_ne_bytes([chunk[0], chunk[1]])),
+                _ => write::<u32>(
+                    addr,
+                    u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+                ),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            MEMGWIDTH => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.width.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            MEMSWIDTH => {
+                let width = unsafe { *(data as *const i32) } as usize;
+                match width {
+                    1 | 2 | 4 => {
+                        self.width.store(width, Ordering::Relaxed);
+                        Ok(0)
+                    }
+                    _ => Err(FsError::InvalidParam),
+                }
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 8,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o600,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(1, 1),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,13 +1,41 @@
 //! Device file system mounted at /dev
 
+mod checksum;
 mod fbdev;
+#[cfg(feature = "board_raspi3")]
+mod mailbox;
+#[cfg(feature = "dev_mem")]
+mod mem;
+#[cfg(feature = "board_raspi3")]
+mod power;
 mod random;
+mod registry;
 mod serial;
 mod shm;
+#[cfg(feature = "board_raspi3")]
+mod system_timer;
+#[cfg(feature = "board_raspi3")]
+mod thermal;
 mod tty;
+#[cfg(feature = "board_raspi3")]
+mod watchdog;
 
+pub use checksum::*;
 pub use fbdev::*;
+#[cfg(feature = "board_raspi3")]
+pub use mailbox::*;
+#[cfg(feature = "dev_mem")]
+pub use mem::*;
+#[cfg(feature = "board_raspi3")]
+pub use power::*;
 pub use random::*;
+pub use registry::*;
 pub use serial::*;
 pub use shm::*;
+#[cfg(feature = "board_raspi3")]
+pub use system_timer::*;
+#[cfg(feature = "board_raspi3")]
+pub use thermal::*;
 pub use tty::*;
+#[cfg(feature = "board_raspi3")]
+pub use watchdog::*;
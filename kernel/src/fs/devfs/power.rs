@@ -0,0 +1,68 @@
+//! Implement INode for the board power control
+
+use crate::arch::board::watchdog;
+use core::any::Any;
+
+use rcore_fs::vfs::*;
+
+/// Reboot or halt the board by writing `reboot` or `halt` to it
+///
+/// The command must match exactly, an optional trailing newline is allowed
+/// so `echo reboot > /dev/power` works. Anything else is rejected.
+#[derive(Default)]
+pub struct PowerINode;
+
+impl INode for PowerINode {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        let cmd = match buf.split_last() {
+            Some((b'\n', cmd)) => cmd,
+            _ => buf,
+        };
+        match cmd {
+            b"reboot" => {
+                warn!("power: reboot requested");
+                watchdog::reboot()
+            }
+            b"halt" => {
+                warn!("power: halt requested");
+                watchdog::halt()
+            }
+            _ => Err(FsError::InvalidParam),
+        }
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 4,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o200,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(10, 2),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
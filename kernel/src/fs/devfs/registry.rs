@@ -0,0 +1,196 @@
+//! Table of device nodes registered to /dev
+//!
+//! Devices register themselves with a name and get an id assigned in
+//! registration order. The console tty is always registered first so it
+//! keeps the fixed id `TTY_ID` used by stdin/stdout/stderr.
+//!
+//! Devices with hardware state to release can also count their opens, they
+//! are told about the first open and the last close.
+//!
+//! Devices can also describe their state, printed when the kernel panics.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::util::text::BoundedWriter;
+use rcore_fs::vfs::*;
+use rcore_fs_devfs::DevFS;
+use spin::{Mutex, RwLock};
+
+/// Id of the console tty backing stdin, stdout and stderr
+pub const TTY_ID: usize = 0;
+
+/// Size limit of the text rendered by `devices_report`
+const REPORT_MAX: usize = 4096;
+
+#[derive(Clone)]
+pub struct DeviceEntry {
+    pub id: usize,
+    pub name: String,
+    pub inode: Arc<dyn INode>,
+}
+
+lazy_static! {
+    /// The device file system mounted at /dev
+    pub static ref DEV_FS: Arc<DevFS> = DevFS::new();
+    // NOTE: RwLock only write when registering devices
+    static ref DEVICES: RwLock<Vec<DeviceEntry>> = RwLock::new(Vec::new());
+    static ref OPEN_COUNTS: Mutex<Vec<OpenCount>> = Mutex::new(Vec::new());
+    static ref STATE_DUMPS: Mutex<Vec<(String, Arc<dyn DeviceState>)>> = Mutex::new(Vec::new());
+}
+
+/// Callbacks of a device which counts its opens
+pub trait DeviceHooks: Send + Sync {
+    /// The device is opened while no file has it open
+    fn first_open(&self) {}
+    /// The last file having the device open is closed
+    fn last_close(&self) {}
+}
+
+/// A device describing its state in crash dumps
+pub trait DeviceState: Send + Sync {
+    /// Write a one line summary of the state to `out`. Called while the
+    /// kernel panics, so locks are only tried, saying which were held.
+    fn dump_state(&self, out: &mut dyn Write) -> core::fmt::Result;
+}
+
+struct OpenCount {
+    rdev: usize,
+    count: usize,
+    hooks: Arc<dyn DeviceHooks>,
+}
+
+/// Register `inode` as /dev/`name`, return the id assigned to it.
+pub fn register_device(name: &str, inode: Arc<dyn INode>) -> Result<usize> {
+    let mut devices = DEVICES.write();
+    if devices.iter().any(|dev| dev.name == name) {
+        return Err(FsError::EntryExist);
+    }
+    DEV_FS.add(name, inode.clone())?;
+    let id = devices.len();
+    devices.push(DeviceEntry {
+        id,
+        name: String::from(name),
+        inode,
+    });
+    Ok(id)
+}
+
+/// Find a registered device by its name.
+pub fn find_device(name: &str) -> Option<DeviceEntry> {
+    DEVICES.read().iter().find(|dev| dev.name == name).cloned()
+}
+
+/// Get a registered device by its id.
+pub fn get_device(id: usize) -> Option<DeviceEntry> {
+    DEVICES.read().get(id).cloned()
+}
+
+/// All registered devices, ordered by id.
+pub fn devices() -> Vec<DeviceEntry> {
+    DEVICES.read().clone()
+}
+
+/// Count opens of the device node with `rdev`, calling `hooks` on changes.
+/// Files find their device by `rdev`, so it must be unique to the device.
+pub fn register_hooks(rdev: usize, hooks: Arc<dyn DeviceHooks>) {
+    OPEN_COUNTS.lock().push(OpenCount {
+        rdev,
+        count: 0,
+        hooks,
+    });
+}
+
+/// A file of the device node with `rdev` is opened.
+pub fn device_opened(rdev: usize) {
+    let mut counts = OPEN_COUNTS.lock();
+    if let Some(open) = counts.iter_mut().find(|open| open.rdev == rdev) {
+        open.count += 1;
+        if open.count == 1 {
+            open.hooks.first_open();
+        }
+    }
+}
+
+/// A file of the device node with `rdev` is closed.
+pub fn device_closed(rdev: usize) {
+    let mut counts = OPEN_COUNTS.lock();
+    if let Some(open) = counts.iter_mut().find(|open| open.rdev == rdev) {
+        open.count -= 1;
+        if open.count == 0 {
+            open.hooks.last_close();
+        }
+    }
+}
+
+/// Print the state of the device `name` in crash dumps.
+pub fn register_state_dump(name: &str, device: Arc<dyn DeviceState>) {
+    STATE_DUMPS.lock().push((String::from(name), device));
+}
+
+/// Print the state of every device registered by `register_state_dump` to
+/// the console, one line each. For the panic handler, it waits for no lock.
+pub fn dump_device_state() {
+    let dumps = match STATE_DUMPS.try_lock() {
+        Some(dumps) => dumps,
+        None => {
+            println!("device state: table locked");
+            return;
+        }
+    };
+    for (name, device) in dumps.iter() {
+        let mut writer = BoundedWriter::new(REPORT_MAX);
+        let _ = device.dump_state(&mut writer);
+        let mark = if writer.is_truncated() { " ..." } else { "" };
+        println!("device state: {}: {}{}", name, writer.as_str(), mark);
+    }
+}
+
+/// Render the device table as text, one `id name type` line per device.
+/// Past `REPORT_MAX` it ends with a `...` line.
+pub fn devices_report() -> String {
+    let mut writer = BoundedWriter::new(REPORT_MAX);
+    for dev in devices() {
+        let type_ = match dev.inode.metadata().map(|m| m.type_) {
+            Ok(FileType::CharDevice) => "char",
+            Ok(FileType::BlockDevice) => "block",
+            Ok(FileType::Dir) => "dir",
+            Ok(FileType::File) => "file",
+            _ => "other",
+        };
+        let _ = writeln!(writer, "{} {} {}", dev.id, dev.name, type_);
+    }
+    let truncated = writer.is_truncated();
+    let mut report = writer.into_string();
+    // a cut line would read as a device of a wrong name
+    if truncated {
+        report.truncate(report.rfind('\n').map_or(0, |end| end + 1));
+        report += "...\n";
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcore_fs_devfs::special::NullINode;
+
+    #[test]
+    fn registered_devices_are_found_by_name_and_id() {
+        let first = register_device("test-a", Arc::new(NullINode::default())).unwrap();
+        let second = register_device("test-b", Arc::new(NullINode::default())).unwrap();
+        assert!(second > first);
+        assert_eq!(find_device("test-b").map(|dev| dev.id), Some(second));
+        assert_eq!(
+            get_device(first).map(|dev| dev.name),
+            Some(String::from("test-a"))
+        );
+        assert!(find_device("test-c").is_none());
+        assert!(matches!(
+            register_device("test-a", Arc::new(NullINode::default())),
+            Err(FsError::EntryExist)
+        ));
+    }
+}
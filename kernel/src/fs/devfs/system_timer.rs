@@ -0,0 +1,72 @@
+//! Implement INode for the BCM2837 system timer
+
+use crate::arch::board::system_timer::read_counter;
+use crate::fs::ioctl::TIMERIOCDELAY;
+use core::any::Any;
+
+use rcore_fs::vfs::*;
+
+/// Read the free-running microsecond counter as a native-endian `u64`
+#[derive(Default)]
+pub struct SystemTimerINode;
+
+impl INode for SystemTimerINode {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        const LEN: usize = core::mem::size_of::<u64>();
+        if buf.len() < LEN {
+            return Err(FsError::InvalidParam);
+        }
+        buf[..LEN].copy_from_slice(&read_counter().to_ne_bytes());
+        Ok(LEN)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    /// TIMERIOCDELAY returns the microseconds to sleep, for the syscall to
+    /// block on, as devices cannot.
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            TIMERIOCDELAY => {
+                let usecs = unsafe { *(data as *const i32) };
+                if usecs < 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                Ok(usecs as usize)
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 3,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o444,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(10, 1),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
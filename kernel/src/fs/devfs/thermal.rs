@@ -0,0 +1,85 @@
+//! Implement INode for the SoC temperature sensor
+
+use crate::arch::board::mailbox;
+use crate::arch::board::system_timer::read_counter;
+use core::any::Any;
+use spin::Mutex;
+
+use rcore_fs::vfs::*;
+
+/// A reading younger than this many microseconds is reused
+const CACHE_USEC: u64 = 100_000;
+
+/// Read the SoC temperature in millidegrees Celsius as text, like
+/// `48312\n`, fetched through the mailbox
+#[derive(Default)]
+pub struct ThermalINode {
+    /// time of the last reading and the temperature read
+    cache: Mutex<Option<(u64, u32)>>,
+}
+
+impl ThermalINode {
+    fn temperature(&self) -> Result<u32> {
+        let now = read_counter();
+        let mut cache = self.cache.lock();
+        match *cache {
+            Some((time, temp)) if now - time < CACHE_USEC => Ok(temp),
+            _ => {
+                let temp = mailbox::get_temperature().map_err(|err| {
+                    warn!("thermal: cannot get temperature: {:x?}", err);
+                    FsError::DeviceError
+                })?;
+                *cache = Some((now, temp));
+                Ok(temp)
+            }
+        }
+    }
+}
+
+impl INode for ThermalINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let text = format!("{}\n", self.temperature()?);
+        let text = text.as_bytes();
+        if offset >= text.len() {
+            return Ok(0);
+        }
+        let len = buf.len().min(text.len() - offset);
+        buf[..len].copy_from_slice(&text[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 7,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o444,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(10, 3),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
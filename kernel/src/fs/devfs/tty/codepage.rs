@@ -0,0 +1,151 @@
+//! Translation of console output from UTF-8 to a single byte code page, for
+//! terminals which show a legacy one
+//!
+//! ASCII passes unchanged. Other chars become their byte in the code page,
+//! or `?` if it has none, as does invalid UTF-8.
+
+use crate::fs::ioctl::*;
+use alloc::vec::Vec;
+
+/// Printed for chars the code page has no byte for
+const UNMAPPED: u8 = b'?';
+
+/// Code points of the bytes 0x80 to 0xff of code page 437
+const CP437_HIGH: [u16; 128] = [
+    0x00c7, 0x00fc, 0x00e9, 0x00e2, 0x00e4, 0x00e0, 0x00e5, 0x00e7, // 0x80
+    0x00ea, 0x00eb, 0x00e8, 0x00ef, 0x00ee, 0x00ec, 0x00c4, 0x00c5, // 0x88
+    0x00c9, 0x00e6, 0x00c6, 0x00f4, 0x00f6, 0x00f2, 0x00fb, 0x00f9, // 0x90
+    0x00ff, 0x00d6, 0x00dc, 0x00a2, 0x00a3, 0x00a5, 0x20a7, 0x0192, // 0x98
+    0x00e1, 0x00ed, 0x00f3, 0x00fa, 0x00f1, 0x00d1, 0x00aa, 0x00ba, // 0xa0
+    0x00bf, 0x2310, 0x00ac, 0x00bd, 0x00bc, 0x00a1, 0x00ab, 0x00bb, // 0xa8
+    0x2591, 0x2592, 0x2593, 0x2502, 0x2524, 0x2561, 0x2562, 0x2556, // 0xb0
+    0x2555, 0x2563, 0x2551, 0x2557, 0x255d, 0x255c, 0x255b, 0x2510, // 0xb8
+    0x2514, 0x2534, 0x252c, 0x251c, 0x2500, 0x253c, 0x255e, 0x255f, // 0xc0
+    0x255a, 0x2554, 0x2569, 0x2566, 0x2560, 0x2550, 0x256c, 0x2567, // 0xc8
+    0x2568, 0x2564, 0x2565, 0x2559, 0x2558, 0x2552, 0x2553, 0x256b, // 0xd0
+    0x256a, 0x2518, 0x250c, 0x2588, 0x2584, 0x258c, 0x2590, 0x2580, // 0xd8
+    0x03b1, 0x00df, 0x0393, 0x03c0, 0x03a3, 0x03c3, 0x00b5, 0x03c4, // 0xe0
+    0x03a6, 0x0398, 0x03a9, 0x03b4, 0x221e, 0x03c6, 0x03b5, 0x2229, // 0xe8
+    0x2261, 0x00b1, 0x2265, 0x2264, 0x2320, 0x2321, 0x00f7, 0x2248, // 0xf0
+    0x00b0, 0x2219, 0x00b7, 0x221a, 0x207f, 0x00b2, 0x25a0, 0x00a0, // 0xf8
+];
+
+pub struct CodePageEncoder {
+    /// one of the `OUTPUT_ENCODING_*` values
+    encoding: usize,
+    /// code point of the unfinished UTF-8 sequence so far
+    ch: u32,
+    /// continuation bytes it still needs
+    missing: usize,
+}
+
+impl Default for CodePageEncoder {
+    fn default() -> Self {
+        CodePageEncoder {
+            encoding: OUTPUT_ENCODING_UTF8,
+            ch: 0,
+            missing: 0,
+        }
+    }
+}
+
+impl CodePageEncoder {
+    pub fn encoding(&self) -> usize {
+        self.encoding
+    }
+
+    /// Translate to `encoding` from now on, forgetting the unfinished
+    /// sequence.
+    pub fn set_encoding(&mut self, encoding: usize) {
+        self.encoding = encoding;
+        self.missing = 0;
+    }
+
+    /// Translate `buf`, append the bytes to print to `out`. An unfinished
+    /// sequence is kept until the next call.
+    pub fn encode(&mut self, buf: &[u8], out: &mut Vec<u8>) {
+        for &c in buf {
+            if self.missing > 0 {
+                if (0x80..=0xbf).contains(&c) {
+                    self.ch = self.ch << 6 | (c & 0x3f) as u32;
+                    self.missing -= 1;
+                    if self.missing == 0 {
+                        out.push(self.byte_of(self.ch));
+                    }
+                    continue;
+                }
+                // the sequence is cut short, `c` is looked at alone
+                self.missing = 0;
+                out.push(UNMAPPED);
+            }
+            match c {
+                0..=0x7f => out.push(c),
+                0xc2..=0xdf => self.start_sequence((c & 0x1f) as u32, 1),
+                0xe0..=0xef => self.start_sequence((c & 0x0f) as u32, 2),
+                0xf0..=0xf4 => self.start_sequence((c & 0x07) as u32, 3),
+                _ => out.push(UNMAPPED),
+            }
+        }
+    }
+
+    fn start_sequence(&mut self, bits: u32, missing: usize) {
+        self.ch = bits;
+        self.missing = missing;
+    }
+
+    /// The byte showing `ch`, a char outside ASCII, in the code page.
+    fn byte_of(&self, ch: u32) -> u8 {
+        match self.encoding {
+            OUTPUT_ENCODING_CP437 => CP437_HIGH
+                .iter()
+                .position(|&high| high as u32 == ch)
+                .map_or(UNMAPPED, |index| 0x80 + index as u8),
+            _ => UNMAPPED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cp437(text: &str) -> Vec<u8> {
+        let mut encoder = CodePageEncoder::default();
+        encoder.set_encoding(OUTPUT_ENCODING_CP437);
+        let mut out = Vec::new();
+        encoder.encode(text.as_bytes(), &mut out);
+        out
+    }
+
+    #[test]
+    fn box_drawing_chars() {
+        assert_eq!(cp437("┌─┐"), [0xda, 0xc4, 0xbf]);
+        assert_eq!(cp437("│╔╝█"), [0xb3, 0xc9, 0xbc, 0xdb]);
+    }
+
+    #[test]
+    fn ascii_passes_and_unmapped_is_question_mark() {
+        assert_eq!(cp437("a€é"), [b'a', b'?', 0x82]);
+    }
+
+    #[test]
+    fn sequence_split_across_writes() {
+        let mut encoder = CodePageEncoder::default();
+        encoder.set_encoding(OUTPUT_ENCODING_CP437);
+        let mut out = Vec::new();
+        let bytes = "─".as_bytes();
+        encoder.encode(&bytes[..1], &mut out);
+        assert!(out.is_empty());
+        encoder.encode(&bytes[1..], &mut out);
+        assert_eq!(out, [0xc4]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_question_mark() {
+        let mut encoder = CodePageEncoder::default();
+        encoder.set_encoding(OUTPUT_ENCODING_CP437);
+        let mut out = Vec::new();
+        encoder.encode(b"\xe2\x94x\xff", &mut out);
+        assert_eq!(out, [b'?', b'x', b'?']);
+    }
+}
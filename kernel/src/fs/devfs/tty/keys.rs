@@ -0,0 +1,104 @@
+//! Decoding of key escape sequences into key events, for TIOCGKEY
+//!
+//! Special keys arrive as CSI (`ESC [`) or SS3 (`ESC O`) sequences, as sent
+//! by xterm compatible terminals. A CSI sequence may carry the modifiers as
+//! a second parameter, like `ESC [ 1 ; 5 A` for Ctrl+Up.
+
+use crate::fs::ioctl::*;
+
+/// A CSI sequence longer than this is not a key, its ESC is taken alone
+pub const CSI_MAX: usize = 16;
+
+pub enum Decoded {
+    /// a key, made of this many bytes
+    Key(KeyEvent, usize),
+    /// the bytes start a sequence which is not finished yet
+    Incomplete,
+}
+
+/// Decode the key at the start of `bytes`, which must not be empty.
+pub fn decode(bytes: &[u8]) -> Decoded {
+    if bytes[0] != 0x1b {
+        return Decoded::Key(key(bytes[0] as u32, 0), 1);
+    }
+    match bytes.get(1) {
+        None => Decoded::Incomplete,
+        Some(b'[') => decode_csi(bytes),
+        Some(b'O') => match bytes.get(2) {
+            None => Decoded::Incomplete,
+            Some(&c) => match final_key(c) {
+                Some(code) => Decoded::Key(key(code, 0), 3),
+                None => escape(),
+            },
+        },
+        // ESC before another byte is how terminals send Alt
+        Some(&c) => Decoded::Key(key(c as u32, KEYMOD_ALT), 2),
+    }
+}
+
+fn decode_csi(bytes: &[u8]) -> Decoded {
+    let end = match bytes.iter().skip(2).position(|c| (0x40..=0x7e).contains(c)) {
+        Some(pos) => pos + 2,
+        None if bytes.len() < CSI_MAX => return Decoded::Incomplete,
+        None => return escape(),
+    };
+    let mut params = [0u32; 2];
+    for (i, param) in bytes[2..end].split(|&c| c == b';').take(2).enumerate() {
+        for &c in param {
+            if !c.is_ascii_digit() {
+                return escape();
+            }
+            params[i] = params[i]
+                .saturating_mul(10)
+                .saturating_add((c - b'0') as u32);
+        }
+    }
+    let code = match bytes[end] {
+        b'~' => tilde_key(params[0]),
+        c => final_key(c),
+    };
+    match code {
+        // modifiers are sent plus one
+        Some(code) => Decoded::Key(key(code, params[1].saturating_sub(1)), end + 1),
+        None => escape(),
+    }
+}
+
+/// Key of the final byte of `ESC O x` or `ESC [ ... x`
+fn final_key(c: u8) -> Option<u32> {
+    Some(match c {
+        b'A' => KEY_UP,
+        b'B' => KEY_DOWN,
+        b'C' => KEY_RIGHT,
+        b'D' => KEY_LEFT,
+        b'H' => KEY_HOME,
+        b'F' => KEY_END,
+        b'P'..=b'S' => KEY_F1 + (c - b'P') as u32,
+        _ => return None,
+    })
+}
+
+/// Key of `ESC [ n ~`
+fn tilde_key(n: u32) -> Option<u32> {
+    Some(match n {
+        1 | 7 => KEY_HOME,
+        2 => KEY_INSERT,
+        3 => KEY_DELETE,
+        4 | 8 => KEY_END,
+        5 => KEY_PAGEUP,
+        6 => KEY_PAGEDOWN,
+        11..=15 => KEY_F1 + n - 11,
+        17..=21 => KEY_F6 + n - 17,
+        23 | 24 => KEY_F11 + n - 23,
+        _ => return None,
+    })
+}
+
+fn key(key: u32, modifiers: u32) -> KeyEvent {
+    KeyEvent { key, modifiers }
+}
+
+/// An unknown sequence: the ESC alone, the rest are plain bytes
+fn escape() -> Decoded {
+    Decoded::Key(key(0x1b, 0), 1)
+}
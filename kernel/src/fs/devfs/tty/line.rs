@@ -0,0 +1,258 @@
+//! Line editing for canonical mode input
+
+use alloc::vec::Vec;
+
+pub const TAB_WIDTH: usize = 8;
+
+/// Return the display column after `c` is printed at `column`.
+pub fn advance_column(column: usize, c: u8) -> usize {
+    match c {
+        b'\n' | b'\r' => 0,
+        b'\t' => (column / TAB_WIDTH + 1) * TAB_WIDTH,
+        0x08 => column.saturating_sub(1),
+        // control chars and UTF-8 continuation bytes do not move the cursor
+        0..=0x1f | 0x7f | 0x80..=0xbf => column,
+        _ => column + 1,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    Ground,
+    Escape,
+    Csi,
+}
+
+/// Display position of the cursor. Bytes of escape sequences are not
+/// printed, so they do not move it.
+pub struct Cursor {
+    pub column: usize,
+    escape: Escape,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Cursor {
+            column: 0,
+            escape: Escape::Ground,
+        }
+    }
+}
+
+impl Cursor {
+    /// Whether printing `c` moves the cursor one column right.
+    pub fn advances(&self, c: u8) -> bool {
+        self.escape == Escape::Ground && c != 0x1b && advance_column(self.column, c) > self.column
+    }
+
+    /// Move the cursor as printing `c` does.
+    pub fn advance(&mut self, c: u8) {
+        self.escape = match self.escape {
+            Escape::Ground if c == 0x1b => Escape::Escape,
+            Escape::Ground => {
+                self.column = advance_column(self.column, c);
+                Escape::Ground
+            }
+            Escape::Escape if c == b'[' => Escape::Csi,
+            Escape::Csi if !(0x40..=0x7e).contains(&c) => Escape::Csi,
+            Escape::Escape | Escape::Csi => Escape::Ground,
+        };
+    }
+}
+
+/// The line being edited, not yet visible to readers
+///
+/// Edits happen at the edit cursor, which stays at the end of the line
+/// unless moved by the line editor.
+#[derive(Default)]
+pub struct LineBuffer {
+    /// pending bytes, each with the display column where its echo started
+    chars: Vec<(u8, usize)>,
+    /// index of the byte edits happen before
+    cursor: usize,
+}
+
+impl LineBuffer {
+    /// Insert `c` at the edit cursor, moving the cursor past it.
+    pub fn push(&mut self, c: u8, column: usize) {
+        self.chars.insert(self.cursor, (c, column));
+        self.cursor += 1;
+    }
+
+    /// Remove the byte before the edit cursor, return it with the column
+    /// its echo started at.
+    pub fn pop(&mut self) -> Option<(u8, usize)> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        Some(self.chars.remove(self.cursor))
+    }
+
+    /// Remove the char before the edit cursor, as `pop`, returning its
+    /// first byte. If `utf8`, a char is a whole UTF-8 sequence, otherwise a
+    /// byte.
+    pub fn pop_char(&mut self, utf8: bool) -> Option<(u8, usize)> {
+        let mut popped = self.pop()?;
+        // continuation bytes follow the lead byte of their sequence
+        while utf8 && is_continuation(popped.0) {
+            match self.pop() {
+                Some(prev) => popped = prev,
+                None => break,
+            }
+        }
+        Some(popped)
+    }
+
+    /// Remove the char at the edit cursor, return whether there was one.
+    pub fn delete_char(&mut self, utf8: bool) -> bool {
+        let end = self.char_end(utf8);
+        self.chars.drain(self.cursor..end);
+        end > self.cursor
+    }
+
+    /// Move the edit cursor back one char, return the column the echo of
+    /// the char now at the cursor started at.
+    pub fn move_left(&mut self, utf8: bool) -> Option<usize> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        while utf8 && self.cursor > 0 && is_continuation(self.chars[self.cursor].0) {
+            self.cursor -= 1;
+        }
+        Some(self.chars[self.cursor].1)
+    }
+
+    /// Move the edit cursor past one char, return its bytes.
+    pub fn move_right(&mut self, utf8: bool) -> Vec<u8> {
+        let end = self.char_end(utf8);
+        let passed = self.chars[self.cursor..end]
+            .iter()
+            .map(|&(c, _)| c)
+            .collect();
+        self.cursor = end;
+        passed
+    }
+
+    /// Bytes from the edit cursor to the end of the line.
+    pub fn tail(&self) -> Vec<u8> {
+        self.chars[self.cursor..].iter().map(|&(c, _)| c).collect()
+    }
+
+    /// Set where the echo of the byte `offset` bytes after the edit cursor
+    /// started, after it is printed again.
+    pub fn set_tail_column(&mut self, offset: usize, column: usize) {
+        self.chars[self.cursor + offset].1 = column;
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.chars.len()
+    }
+
+    /// Move the edit cursor to the end of the line.
+    pub fn move_end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+
+    /// Take the whole line out, leaving the buffer empty.
+    pub fn take(&mut self) -> Vec<u8> {
+        let line = self.chars.iter().map(|&(c, _)| c).collect();
+        self.chars.clear();
+        self.cursor = 0;
+        line
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Index just past the char at the edit cursor.
+    fn char_end(&self, utf8: bool) -> usize {
+        let mut end = (self.cursor + 1).min(self.chars.len());
+        while utf8 && end < self.chars.len() && is_continuation(self.chars[end].0) {
+            end += 1;
+        }
+        end
+    }
+}
+
+fn is_continuation(c: u8) -> bool {
+    (0x80..=0xbf).contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type `bytes` into `line`, echoing them on `cursor`.
+    fn type_in(line: &mut LineBuffer, cursor: &mut Cursor, bytes: &[u8]) {
+        for &c in bytes {
+            line.push(c, cursor.column);
+            cursor.advance(c);
+        }
+    }
+
+    #[test]
+    fn erase_multibyte_char() {
+        let mut line = LineBuffer::default();
+        let mut cursor = Cursor::default();
+        type_in(&mut line, &mut cursor, "aé".as_bytes());
+        assert_eq!(cursor.column, 2);
+        let (c, column) = line.pop_char(true).unwrap();
+        assert_eq!(c, 0xc3);
+        // one backspace takes the echo back over the char
+        assert_eq!(cursor.column - column, 1);
+        assert_eq!(line.take(), b"a");
+    }
+
+    #[test]
+    fn erase_bytewise_without_utf8() {
+        let mut line = LineBuffer::default();
+        let mut cursor = Cursor::default();
+        type_in(&mut line, &mut cursor, "aé".as_bytes());
+        assert_eq!(line.pop_char(false).map(|(c, _)| c), Some(0xa9));
+        assert_eq!(line.take(), [b'a', 0xc3]);
+    }
+
+    #[test]
+    fn insert_in_middle() {
+        let mut line = LineBuffer::default();
+        let mut cursor = Cursor::default();
+        type_in(&mut line, &mut cursor, b"ac");
+        assert_eq!(line.move_left(true), Some(1));
+        assert!(!line.at_end());
+        line.push(b'b', 1);
+        // the tail is echoed again after the inserted char
+        assert_eq!(line.tail(), b"c");
+        assert_eq!(line.take(), b"abc");
+    }
+
+    #[test]
+    fn move_then_erase() {
+        let mut line = LineBuffer::default();
+        let mut cursor = Cursor::default();
+        type_in(&mut line, &mut cursor, b"abc");
+        line.move_left(true);
+        assert_eq!(line.pop_char(true), Some((b'b', 1)));
+        assert_eq!(line.tail(), b"c");
+        assert_eq!(line.move_right(true), b"c");
+        assert!(line.at_end());
+        assert_eq!(line.take(), b"ac");
+    }
+
+    #[test]
+    fn delete_at_cursor() {
+        let mut line = LineBuffer::default();
+        let mut cursor = Cursor::default();
+        type_in(&mut line, &mut cursor, "xé".as_bytes());
+        line.move_left(true);
+        assert!(line.delete_char(true));
+        assert!(!line.delete_char(true));
+        assert_eq!(line.take(), b"x");
+    }
+}
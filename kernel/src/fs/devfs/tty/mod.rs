@@ -0,0 +1,2267 @@
+use self::codepage::CodePageEncoder;
+use self::keys::{Decoded, CSI_MAX};
+use self::line::*;
+use self::rate::RateLimit;
+use self::screen::Screen;
+use self::sgr::SgrFilter;
+use self::utf8::{Utf8Decoder, DECODED_MAX};
+use crate::drivers::serial;
+use crate::fs::devfs::DeviceState;
+use crate::fs::ioctl::*;
+use crate::fs::queue::ByteQueue;
+use crate::process::{process_group, Pgid};
+use crate::signal::{send_signal, Signal};
+use crate::signal::{Siginfo, SI_KERNEL};
+use crate::util::text::hex_dump;
+use crate::{sync::SpinNoIrqLock, syscall::SysError};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::Write;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::Context;
+use core::task::Poll;
+use core::time::Duration;
+use rcore_fs::vfs::FsError::NotSupported;
+use rcore_fs::vfs::*;
+use spin::Mutex;
+
+mod codepage;
+mod keys;
+mod line;
+mod rate;
+mod screen;
+mod sgr;
+mod utf8;
+
+/// Writes up to this size are printed without interleaving with other writers
+pub const PIPE_BUF: usize = 4096;
+
+/// Input taking longer to become readable is reported in debug builds
+const INPUT_LATENCY_WARN: Duration = Duration::from_millis(1);
+
+/// An unfinished escape sequence older than this is taken as a lone ESC
+const ESC_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Upper bound of the delay between echoed chars, echo happens in
+/// interrupt handlers
+const ECHO_DELAY_MAX: Duration = Duration::from_millis(100);
+/// Echo queued behind `echo_delay` kept at most, more is printed at once
+const PACED_ECHO_MAX: usize = 4096;
+
+/// Resize and signal events kept at most, older ones are dropped
+const EVENTS_MAX: usize = 64;
+
+/// Bytes of unread input and pending line kept at most, by default
+const INPUT_MAX: usize = 4096;
+
+/// Upper bound of the input capacity programs can ask for
+const INPUT_MAX_LIMIT: usize = 0x10000;
+
+/// Default length limit of the pending line in canonical mode
+const MAX_CANON: usize = 4096;
+
+/// Upper bound of the size of the input recording ring
+const RECORD_MAX: usize = 0x10000;
+
+/// Bytes of captured output kept at most, later ones are dropped
+const CAPTURE_MAX: usize = 0x10000;
+
+/// Bytes held back from other writers during exclusive output at most,
+/// later ones are dropped
+const HELD_MAX: usize = 0x10000;
+
+/// Output printed while capturing, see TIOCSCAPTURE
+#[derive(Default)]
+struct OutputCapture {
+    bytes: VecDeque<u8>,
+    /// bytes not kept since the last TIOCGCAPTURE, as `bytes` was full
+    dropped: usize,
+}
+
+/// The open file holding exclusive output of a tty
+struct ExclusiveOwner {
+    /// identifies the open file description
+    token: usize,
+    /// address of the process which opened it, its writes are printed.
+    /// Writers hold their process locked, so it is told by address, not pid
+    process: usize,
+}
+
+/// console tty
+// Ref: [https://linux.die.net/man/4/tty]
+pub struct TtyINode {
+    /// foreground process group
+    // NOTE: read from interrupt handlers by `push`
+    foreground_pgid: SpinNoIrqLock<Pgid>,
+    /// input readable by programs
+    // NOTE: pushed to from interrupt handlers
+    input: SpinNoIrqLock<ByteQueue>,
+    /// `input` or `eofs` is not empty, for polls not to take its lock. Only
+    /// changed with `input` locked
+    readable: AtomicBool,
+    /// input sequence numbers an end of file was typed at in canonical
+    /// mode. A read there returns 0 once, later reads wait for input again.
+    /// Only changed with `input` locked
+    eofs: SpinNoIrqLock<VecDeque<usize>>,
+    /// when the input last made readable was received
+    last_input: SpinNoIrqLock<Duration>,
+    /// sequence number of the next input byte made readable or dropped
+    input_seq: AtomicUsize,
+    /// queue resize and signal events for TIOCGEVENT
+    event_mode: AtomicBool,
+    /// resize and signal events not yet taken
+    events: SpinNoIrqLock<VecDeque<TtyEvent>>,
+    /// line being edited in canonical mode
+    // NOTE: edited from interrupt handlers by `push`, as is `edit_escape`
+    line: SpinNoIrqLock<LineBuffer>,
+    /// move within the pending line on arrow keys
+    line_edit: AtomicBool,
+    /// escape sequence being typed in line edit mode
+    edit_escape: SpinNoIrqLock<Vec<u8>>,
+    /// display position of the cursor
+    // NOTE: used from interrupt handlers by echo, as is the echo state below
+    cursor: SpinNoIrqLock<Cursor>,
+    /// time between echoed chars, for slow displays
+    echo_delay: SpinNoIrqLock<Duration>,
+    /// when the last char was echoed
+    last_echo: SpinNoIrqLock<Duration>,
+    /// echo waiting for `echo_delay` to pass, printed by `release_echo`
+    paced_echo: SpinNoIrqLock<VecDeque<u8>>,
+    // NOTE: both read from interrupt handlers by `push`
+    winsize: SpinNoIrqLock<Winsize>,
+    termios: SpinNoIrqLock<Termios>,
+    /// writes longer than this may interleave with other writers
+    atomic_write_size: AtomicUsize,
+    /// written but not yet printed output, also printed before reading input
+    /// and on fsync whatever the settings below are, as far as `out_rate`
+    /// allows
+    pending: Mutex<Vec<u8>>,
+    /// `pending` has output the rate limit holds back, writes wait for it.
+    /// Only changed with `pending` locked
+    rate_held: AtomicBool,
+    /// limit of the rate written output is printed at
+    // NOTE: also taken from the timer interrupt by `release_output`
+    out_rate: SpinNoIrqLock<RateLimit>,
+    /// translation of console output to the output encoding
+    encoder: SpinNoIrqLock<CodePageEncoder>,
+    /// pending output is printed once it reaches this size, 0 for unbuffered
+    flush_threshold: AtomicUsize,
+    /// print pending output at every newline
+    autoflush: AtomicBool,
+    /// color state of written output
+    sgr: Mutex<SgrFilter>,
+    /// bumped by `cancel_reads`, fails reads waiting since before
+    read_generation: AtomicUsize,
+    /// bytes of written output printed since boot, or the last reset
+    bytes_written: AtomicUsize,
+    /// prefix each written line with the time it was written
+    line_stamps: AtomicBool,
+    /// the next written byte starts a line, only changed with `sgr` locked
+    line_start: AtomicBool,
+    /// wrap output lines at the window width
+    wrap: AtomicBool,
+    /// show written output as a hex dump
+    hexdump: AtomicBool,
+    /// offset of the next written byte in the hex dump
+    hexdump_offset: AtomicUsize,
+    /// bytes of unread input and pending line kept at most
+    input_max: AtomicUsize,
+    /// what happens to input arriving while the input buffer is full,
+    /// one of the `OVERFLOW_*` policies
+    overflow: AtomicUsize,
+    /// input bytes dropped as the input buffer was full
+    drops: AtomicUsize,
+    /// keep input arriving while no one waits for it
+    type_ahead: AtomicBool,
+    /// readers and pollers waiting for input
+    waiting: AtomicUsize,
+    /// length limit of the pending line in canonical mode
+    max_canon: AtomicUsize,
+    /// what happens to the pending line at `max_canon`, one of the
+    /// `CANON_FULL_*` policies
+    canon_full: AtomicUsize,
+    /// checks canonical mode input as UTF-8 if IUTF8 is set
+    utf8: SpinNoIrqLock<Utf8Decoder>,
+    /// bytes received while recording, oldest first
+    record: SpinNoIrqLock<VecDeque<u8>>,
+    /// size of the recording ring, 0 when not recording. Only changed with
+    /// `record` locked
+    record_max: AtomicUsize,
+    /// copy of the output printed to the console, if capturing
+    // NOTE: printed to from interrupt handlers by echo
+    capture: SpinNoIrqLock<Option<OutputCapture>>,
+    /// grid of what the console shows, if keeping it
+    // NOTE: printed to from interrupt handlers by echo
+    screen: SpinNoIrqLock<Option<Screen>>,
+    /// take `\r`, `\n` and `\r\n` alike as one `\n`, before the iflag
+    /// conversions
+    newline_fold: AtomicBool,
+    /// the last byte received was `\r`, for `newline_fold`
+    after_cr: AtomicBool,
+    /// a read stops after the first `\n` in any mode
+    line_read: AtomicBool,
+    /// holder of exclusive output, writes of other processes are held back
+    exclusive: Mutex<Option<ExclusiveOwner>>,
+    /// writes held back during exclusive output, printed when it ends
+    held: Mutex<Vec<u8>>,
+    /// bytes dropped as `held` was full
+    held_dropped: AtomicUsize,
+}
+
+impl Default for TtyINode {
+    fn default() -> Self {
+        TtyINode {
+            foreground_pgid: SpinNoIrqLock::default(),
+            input: SpinNoIrqLock::default(),
+            readable: AtomicBool::new(false),
+            eofs: SpinNoIrqLock::default(),
+            last_input: SpinNoIrqLock::default(),
+            input_seq: AtomicUsize::new(0),
+            event_mode: AtomicBool::new(false),
+            events: SpinNoIrqLock::default(),
+            line: SpinNoIrqLock::default(),
+            line_edit: AtomicBool::new(false),
+            edit_escape: SpinNoIrqLock::default(),
+            cursor: SpinNoIrqLock::default(),
+            echo_delay: SpinNoIrqLock::default(),
+            last_echo: SpinNoIrqLock::default(),
+            paced_echo: SpinNoIrqLock::default(),
+            winsize: SpinNoIrqLock::default(),
+            termios: SpinNoIrqLock::default(),
+            atomic_write_size: AtomicUsize::new(PIPE_BUF),
+            pending: Mutex::default(),
+            rate_held: AtomicBool::new(false),
+            out_rate: SpinNoIrqLock::default(),
+            encoder: SpinNoIrqLock::default(),
+            flush_threshold: AtomicUsize::new(PIPE_BUF),
+            autoflush: AtomicBool::new(true),
+            sgr: Mutex::default(),
+            read_generation: AtomicUsize::new(0),
+            bytes_written: AtomicUsize::new(0),
+            line_stamps: AtomicBool::new(false),
+            line_start: AtomicBool::new(true),
+            wrap: AtomicBool::new(false),
+            hexdump: AtomicBool::new(false),
+            hexdump_offset: AtomicUsize::new(0),
+            input_max: AtomicUsize::new(INPUT_MAX),
+            overflow: AtomicUsize::new(OVERFLOW_BELL),
+            drops: AtomicUsize::new(0),
+            type_ahead: AtomicBool::new(true),
+            waiting: AtomicUsize::new(0),
+            max_canon: AtomicUsize::new(MAX_CANON),
+            canon_full: AtomicUsize::new(CANON_FULL_BELL),
+            utf8: SpinNoIrqLock::default(),
+            record: SpinNoIrqLock::default(),
+            record_max: AtomicUsize::new(0),
+            capture: SpinNoIrqLock::new(None),
+            screen: SpinNoIrqLock::new(None),
+            newline_fold: AtomicBool::new(false),
+            after_cr: AtomicBool::new(false),
+            line_read: AtomicBool::new(false),
+            exclusive: Mutex::new(None),
+            held: Mutex::default(),
+            held_dropped: AtomicUsize::new(0),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref TTY: Arc<TtyINode> = Arc::new(TtyINode::default());
+}
+
+/// Print the echo of the console tty queued behind its echo delay as it
+/// allows. Called from the timer interrupt on every tick.
+pub fn release_echo() {
+    TTY.release_echo();
+}
+
+/// Print the output of the console tty held back by its output rate limit
+/// as it allows. Called from the timer interrupt on every tick.
+pub fn release_output() {
+    TTY.release_output();
+}
+
+pub fn foreground_pgid() -> Pgid {
+    *TTY.foreground_pgid.lock()
+}
+
+/// Render the special chars of the console tty as text, one `NAME=value`
+/// line each, control chars as `^X`, for /proc/tty/cc.
+pub fn cc_report() -> String {
+    let cc = TTY.termios.lock().cc;
+    render_cc(&cc)
+}
+
+/// Render the special chars `cc` as `cc_report` does.
+fn render_cc(cc: &[u8; 32]) -> String {
+    const NAMES: [(&str, usize); 17] = [
+        ("VINTR", VINTR),
+        ("VQUIT", VQUIT),
+        ("VERASE", VERASE),
+        ("VKILL", VKILL),
+        ("VEOF", VEOF),
+        ("VTIME", VTIME),
+        ("VMIN", VMIN),
+        ("VSWTC", VSWTC),
+        ("VSTART", VSTART),
+        ("VSTOP", VSTOP),
+        ("VSUSP", VSUSP),
+        ("VEOL", VEOL),
+        ("VREPRINT", VREPRINT),
+        ("VDISCARD", VDISCARD),
+        ("VWERASE", VWERASE),
+        ("VLNEXT", VLNEXT),
+        ("VEOL2", VEOL2),
+    ];
+    let mut report = String::new();
+    for &(name, index) in NAMES.iter() {
+        let c = cc[index];
+        let _ = match (index, c) {
+            // counts, not chars
+            (VTIME, _) | (VMIN, _) => writeln!(report, "{}={}", name, c),
+            (_, POSIX_VDISABLE) => writeln!(report, "{}=undef", name),
+            (_, 0x7f) => writeln!(report, "{}=^?", name),
+            (_, 0..=0x1f) => writeln!(report, "{}=^{}", name, (c ^ 0x40) as char),
+            (_, 0x20..=0x7e) => writeln!(report, "{}={}", name, c as char),
+            _ => writeln!(report, "{}={:#04x}", name, c),
+        };
+    }
+    report
+}
+
+/// Send `signal` to every process in the process group `pgid`.
+fn signal_group(pgid: Pgid, signal: Signal) {
+    let signo = signal as i32;
+    for proc in process_group(pgid) {
+        send_signal(
+            proc,
+            -1,
+            Siginfo {
+                signo,
+                errno: 0,
+                code: SI_KERNEL,
+                field: Default::default(),
+            },
+        );
+    }
+}
+
+impl TtyINode {
+    /// Handle a received byte. Called from interrupt handlers.
+    ///
+    /// Input is made readable before it is echoed: echoing prints to the
+    /// console, which waits for the log lock under heavy output, and
+    /// waking readers must not wait for that.
+    ///
+    /// Return false if the input buffer is full and the overflow policy is
+    /// `OVERFLOW_ERROR`, the byte is then left to the caller. Other policies
+    /// drop it.
+    ///
+    /// The byte is handled under one copy of termios, a TCSETS meanwhile
+    /// applies from the next byte on.
+    pub fn push(&self, c: u8) -> bool {
+        let received = crate::arch::timer::timer_now();
+        self.record_input(c);
+        let termios = *self.termios.lock();
+        if !checks_utf8(&termios) {
+            return self.push_decoded(c, &termios, received);
+        }
+        let mut decoded = [0; DECODED_MAX];
+        let (before, len) = {
+            let mut utf8 = self.utf8.lock();
+            (*utf8, utf8.push_byte(c, &mut decoded))
+        };
+        // a char which does not fit whole overflows whole, so its bytes are
+        // never split by a refusal
+        if len > 1 && self.input_room() < len {
+            if self.overflow(len) {
+                return true;
+            }
+            // the producer pushes `c` again, it must find the decoder as
+            // it was
+            *self.utf8.lock() = before;
+            return false;
+        }
+        for (i, &c) in decoded[..len].iter().enumerate() {
+            if self.push_decoded(c, &termios, received) {
+                continue;
+            }
+            if i == 0 {
+                *self.utf8.lock() = before;
+                return false;
+            }
+            // the bytes before are taken, pushing `c` again would repeat
+            // them. The rest is lost
+            self.count_dropped(len - i);
+            return true;
+        }
+        true
+    }
+
+    /// Handle a byte given back by the UTF-8 check, see `push`.
+    fn push_decoded(&self, mut c: u8, termios: &Termios, received: Duration) -> bool {
+        let iflag = InputModes::from_bits_truncate(termios.iflag);
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        // bytes pass unchanged if none of these are set, as after cfmakeraw()
+        if iflag.contains(InputModes::ISTRIP) {
+            c &= 0x7f;
+        }
+        if self.newline_fold.load(Ordering::Relaxed) {
+            let after_cr = self.after_cr.swap(c == b'\r', Ordering::Relaxed);
+            c = match fold_newline(c, after_cr) {
+                Some(c) => c,
+                None => return true,
+            };
+        } else if c == b'\r' {
+            if iflag.contains(InputModes::IGNCR) {
+                return true;
+            } else if iflag.contains(InputModes::ICRNL) {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag.contains(InputModes::INLCR) {
+            c = b'\r';
+        }
+        let is_cc = |index: usize| c != POSIX_VDISABLE && termios.cc[index] == c;
+        if lflag.contains(LocalModes::ISIG) {
+            if is_cc(VINTR) {
+                self.signal_foreground(Signal::SIGINT);
+                self.push_event(TTY_EVENT_SIGNAL, [Signal::SIGINT as u32, 0]);
+                return true;
+            } else if is_cc(VQUIT) || is_cc(VSUSP) {
+                warn!("special char {} is unimplented", c);
+                return true;
+            }
+        }
+        // without type-ahead, input is only kept while someone waits for it
+        if !self.type_ahead.load(Ordering::Relaxed) && self.waiting.load(Ordering::SeqCst) == 0 {
+            self.input_seq.fetch_add(1, Ordering::SeqCst);
+            return true;
+        }
+        // erasing still works while full, it makes room
+        let erases = lflag.contains(LocalModes::ICANON) && (is_cc(VERASE) || is_cc(VKILL));
+        if !erases && self.input_full() {
+            return self.overflow(1);
+        }
+        if lflag.contains(LocalModes::ICANON) {
+            if let Some(line) = self.push_canonical(c, termios, lflag) {
+                self.commit_input(&line, received);
+                let echo_nl = c == b'\n' && lflag.contains(LocalModes::ECHONL);
+                // the end of file char is not echoed
+                if (lflag.contains(LocalModes::ECHO) || echo_nl) && !is_cc(VEOF) {
+                    self.echo(c, lflag);
+                }
+            }
+        } else {
+            self.commit_input(&[c], received);
+            if lflag.contains(LocalModes::ECHO) {
+                self.echo(c, lflag);
+            }
+        }
+        true
+    }
+
+    /// Whether unread input and the pending line reached the capacity.
+    fn input_full(&self) -> bool {
+        self.input_room() == 0
+    }
+
+    /// Number of bytes the input can take before it is full.
+    fn input_room(&self) -> usize {
+        let unread = self.input.lock().len();
+        let max = self.input_max.load(Ordering::Relaxed);
+        max.saturating_sub(unread + self.line.lock().len())
+    }
+
+    /// Count `count` input bytes as dropped. Also for producers which can
+    /// not keep what `push` refused.
+    pub fn count_dropped(&self, count: usize) {
+        self.input_seq.fetch_add(count, Ordering::SeqCst);
+        self.drops.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Change the input capacity to `max` bytes, clamped to
+    /// `INPUT_MAX_LIMIT`. Input already held is kept, so shrinking below it
+    /// fails with `Busy`.
+    fn set_input_max(&self, max: usize) -> Result<()> {
+        // pushes check the capacity under the input lock too
+        let input = self.input.lock();
+        let max = clamp_input_max(max, input.len() + self.line.lock().len())?;
+        self.input_max.store(max, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Handle `count` bytes of a char arriving while the input buffer is
+    /// full, return whether they count as taken.
+    fn overflow(&self, count: usize) -> bool {
+        let (taken, bell) = overflow_action(self.overflow.load(Ordering::Relaxed));
+        if taken {
+            self.count_dropped(count);
+        }
+        if bell {
+            self.echo_raw(b"\x07");
+        }
+        taken
+    }
+
+    /// Edit the pending line with `c`, return the finished line on newline
+    /// or the VEOL or VEOL2 char, which stay at its end like a newline, or
+    /// when it reaches `max_canon` if the policy delivers it then.
+    /// The caller echoes `c` if a line is returned.
+    fn push_canonical(&self, c: u8, termios: &Termios, lflag: LocalModes) -> Option<Vec<u8>> {
+        let echo = lflag.contains(LocalModes::ECHO);
+        let is_cc = |index: usize| c != POSIX_VDISABLE && termios.cc[index] == c;
+        let utf8 = InputModes::from_bits_truncate(termios.iflag).contains(InputModes::IUTF8);
+        if self.line_edit.load(Ordering::Relaxed)
+            && (c == 0x1b || !self.edit_escape.lock().is_empty())
+        {
+            return self.edit_escape(c, termios, lflag);
+        }
+        if is_cc(VERASE) {
+            self.erase(1, echo && lflag.contains(LocalModes::ECHOE), utf8, lflag);
+        } else if is_cc(VKILL) {
+            let len = self.line.lock().len();
+            self.erase(len, echo && lflag.contains(LocalModes::ECHOK), false, lflag);
+        } else if c == b'\n' || is_cc(VEOL) || is_cc(VEOL2) {
+            let mut line = self.line.lock();
+            // the line ends at its end wherever it was edited
+            line.move_end();
+            line.push(c, 0);
+            return Some(line.take());
+        } else if is_cc(VEOF) {
+            // ends the line without a newline, or the input if it is empty
+            let line = self.line.lock().take();
+            if !line.is_empty() {
+                return Some(line);
+            }
+            self.push_eof();
+        } else {
+            return self.insert_char(c, lflag);
+        }
+        None
+    }
+
+    /// Insert `c` into the pending line at the edit cursor, return the line
+    /// if the policy delivers it at `max_canon`.
+    fn insert_char(&self, c: u8, lflag: LocalModes) -> Option<Vec<u8>> {
+        let max_canon = self.max_canon.load(Ordering::Relaxed);
+        let policy = self.canon_full.load(Ordering::Relaxed);
+        let column = self.cursor.lock().column;
+        let mut line = self.line.lock();
+        let (refused, deliver) = line_limit_action(policy, line.len(), max_canon);
+        // a newline is still taken by a full line
+        if refused {
+            drop(line);
+            self.echo_raw(b"\x07");
+            return None;
+        }
+        line.push(c, column);
+        if deliver {
+            return Some(line.take());
+        }
+        let at_end = line.at_end();
+        drop(line);
+        if lflag.contains(LocalModes::ECHO) {
+            self.echo(c, lflag);
+            if !at_end {
+                self.redraw_tail(lflag);
+            }
+        }
+        None
+    }
+
+    /// Collect `c` into the escape sequence typed in line edit mode. The
+    /// arrow, Home, End and Delete keys edit the line, other sequences are
+    /// taken as typed.
+    fn edit_escape(&self, c: u8, termios: &Termios, lflag: LocalModes) -> Option<Vec<u8>> {
+        let mut escape = self.edit_escape.lock();
+        if (c < 0x20 || c == 0x7f) && c != 0x1b {
+            // a control char, as Enter or Backspace, ends it unfinished
+            let typed = core::mem::replace(&mut *escape, Vec::new());
+            drop(escape);
+            self.take_typed(&typed, termios, lflag);
+            return self.push_canonical(c, termios, lflag);
+        }
+        escape.push(c);
+        let (key, len) = match keys::decode(&escape) {
+            Decoded::Incomplete => return None,
+            Decoded::Key(key, len) => (key, len),
+        };
+        let typed = core::mem::replace(&mut *escape, Vec::new());
+        drop(escape);
+        if key.modifiers != 0 || len != typed.len() || !self.edit_key(key.key, termios, lflag) {
+            self.take_typed(&typed, termios, lflag);
+        }
+        None
+    }
+
+    /// Take `typed`, an escape sequence which is no editing key, as input:
+    /// its ESC as a char of the line, the bytes after as if typed again.
+    fn take_typed(&self, typed: &[u8], termios: &Termios, lflag: LocalModes) {
+        let received = crate::arch::timer::timer_now();
+        let (&esc, rest) = match typed.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut line = self.insert_char(esc, lflag);
+        for &c in rest {
+            if let Some(line) = line.take() {
+                self.commit_input(&line, received);
+            }
+            line = self.push_canonical(c, termios, lflag);
+        }
+        if let Some(line) = line {
+            self.commit_input(&line, received);
+        }
+    }
+
+    /// Move within or edit the pending line for `key`, return false if it
+    /// is no editing key.
+    fn edit_key(&self, key: u32, termios: &Termios, lflag: LocalModes) -> bool {
+        let utf8 = InputModes::from_bits_truncate(termios.iflag).contains(InputModes::IUTF8);
+        match key {
+            KEY_LEFT => {
+                self.move_left(lflag, utf8);
+            }
+            KEY_RIGHT => {
+                self.move_right(lflag, utf8);
+            }
+            KEY_HOME => while self.move_left(lflag, utf8) {},
+            KEY_END => while self.move_right(lflag, utf8) {},
+            KEY_DELETE => {
+                let deleted = self.line.lock().delete_char(utf8);
+                if deleted && lflag.contains(LocalModes::ECHO) {
+                    self.redraw_tail(lflag);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Move the edit cursor back one char, return whether it moved.
+    fn move_left(&self, lflag: LocalModes, utf8: bool) -> bool {
+        let start = match self.line.lock().move_left(utf8) {
+            Some(start) => start,
+            None => return false,
+        };
+        let mut cursor = self.cursor.lock();
+        if lflag.contains(LocalModes::ECHO) {
+            for _ in start..cursor.column {
+                self.echo_raw(b"\x08");
+            }
+        }
+        cursor.column = start;
+        true
+    }
+
+    /// Move the edit cursor past one char, printing it again, return
+    /// whether it moved.
+    fn move_right(&self, lflag: LocalModes, utf8: bool) -> bool {
+        let passed = self.line.lock().move_right(utf8);
+        if lflag.contains(LocalModes::ECHO) {
+            for &c in &passed {
+                self.echo(c, lflag);
+            }
+        }
+        !passed.is_empty()
+    }
+
+    /// Print the pending line again from the edit cursor on after it changed
+    /// there, clear what is left of the old line, and put the display
+    /// cursor back at the edit cursor.
+    fn redraw_tail(&self, lflag: LocalModes) {
+        let tail = self.line.lock().tail();
+        let start = self.cursor.lock().column;
+        for (i, &c) in tail.iter().enumerate() {
+            let column = self.cursor.lock().column;
+            self.line.lock().set_tail_column(i, column);
+            self.echo(c, lflag);
+        }
+        // erase to the end of the display line
+        self.echo_raw(b"\x1b[K");
+        let mut cursor = self.cursor.lock();
+        for _ in start..cursor.column {
+            self.echo_raw(b"\x08");
+        }
+        cursor.column = start;
+    }
+
+    /// Mark an end of file after the input made readable so far, waking
+    /// readers.
+    fn push_eof(&self) {
+        let mut input = self.input.lock();
+        let seq = self.input_seq.load(Ordering::SeqCst);
+        self.eofs.lock().push_back(seq);
+        self.input_changed(&input);
+        input.wake_all();
+    }
+
+    /// Make `bytes` readable, waking readers. Only the input queue is locked.
+    /// Debug builds warn if this happens long after the byte was `received`.
+    fn commit_input(&self, bytes: &[u8], received: Duration) {
+        {
+            // numbered under the lock, so numbers grow along the queue
+            let mut input = self.input.lock();
+            let seq = self.input_seq.fetch_add(bytes.len(), Ordering::SeqCst);
+            input.push_numbered(bytes, seq);
+            self.input_changed(&input);
+        }
+        *self.last_input.lock() = received;
+        if cfg!(debug_assertions) {
+            let now = crate::arch::timer::timer_now();
+            let latency = now.checked_sub(received).unwrap_or_default();
+            if latency > INPUT_LATENCY_WARN {
+                warn!(
+                    "tty: input became readable {:?} after it was received",
+                    latency
+                );
+            }
+        }
+    }
+
+    /// Erase up to `count` chars from the pending line, whole UTF-8
+    /// sequences if `utf8`, bytes otherwise.
+    /// If `echo`, move the cursor back over their display width, `lflag`
+    /// are the local modes the input byte is handled under.
+    fn erase(&self, count: usize, echo: bool, utf8: bool, lflag: LocalModes) {
+        let mut erased = false;
+        for _ in 0..count {
+            let (_, start) = match self.line.lock().pop_char(utf8) {
+                Some(erased) => erased,
+                None => break,
+            };
+            erased = true;
+            let at_end = self.line.lock().at_end();
+            let mut cursor = self.cursor.lock();
+            if echo {
+                // before the end, the rest of the line is printed over it
+                let rubout: &[u8] = if at_end { b"\x08 \x08" } else { b"\x08" };
+                for _ in start..cursor.column {
+                    self.echo_raw(rubout);
+                }
+            }
+            cursor.column = start;
+        }
+        if echo && erased && !self.line.lock().at_end() {
+            self.redraw_tail(lflag);
+        }
+    }
+
+    /// Echo an input byte, showing control chars as `^X` if ECHOCTL is set.
+    fn echo(&self, c: u8, lflag: LocalModes) {
+        let is_ctrl = (c < 0x20 || c == 0x7f) && c != b'\n' && c != b'\t';
+        if lflag.contains(LocalModes::ECHOCTL) && is_ctrl {
+            self.output_to(&[b'^', c ^ 0x40], true);
+        } else {
+            self.output_to(&[c], true);
+        }
+    }
+
+    /// Print echo which moves the cursor by itself.
+    fn echo_raw(&self, buf: &[u8]) {
+        self.print_echo(buf);
+    }
+
+    /// Print echo to the console. With an `echo_delay` it is queued
+    /// instead, as echo runs in interrupt handlers which must not wait, and
+    /// printed by `release_echo`.
+    fn print_echo(&self, buf: &[u8]) {
+        let paced = *self.echo_delay.lock() != Duration::default();
+        {
+            let mut queue = self.paced_echo.lock();
+            // echo queued before goes first, even if the delay is gone now
+            if paced || !queue.is_empty() {
+                if queue.len() + buf.len() <= PACED_ECHO_MAX {
+                    queue.extend(buf);
+                    drop(queue);
+                    self.release_echo();
+                    return;
+                }
+                // too far behind, pacing gives up on what is queued
+                let queued: Vec<u8> = queue.drain(..).collect();
+                drop(queue);
+                self.print_console(&queued);
+            }
+        }
+        self.print_console(buf);
+    }
+
+    /// Print queued echo whose `echo_delay` passed, a char per delay since
+    /// the last one. Called on every timer tick and when echo is queued.
+    pub fn release_echo(&self) {
+        let delay = *self.echo_delay.lock();
+        let now = crate::arch::timer::timer_now();
+        let mut due = Vec::new();
+        {
+            let mut queue = self.paced_echo.lock();
+            let mut last_echo = self.last_echo.lock();
+            while !queue.is_empty() && now >= *last_echo + delay {
+                // an idle time is no credit for a burst later
+                *last_echo = if now - *last_echo >= delay * 2 {
+                    now
+                } else {
+                    *last_echo + delay
+                };
+                due.extend(queue.pop_front());
+                if delay == Duration::default() {
+                    due.extend(queue.drain(..));
+                }
+            }
+        }
+        if !due.is_empty() {
+            self.print_console(&due);
+        }
+    }
+
+    /// Print `buf` to the console, tracking the cursor column.
+    /// If wrapping is on, a line reaching the window width continues on the
+    /// next line.
+    fn output(&self, buf: &[u8]) {
+        self.output_to(buf, false);
+    }
+
+    /// Print `buf` like `output`, as echo if `echo`, see `print_echo`.
+    fn output_to(&self, buf: &[u8], echo: bool) {
+        let width = if self.wrap.load(Ordering::Relaxed) {
+            self.winsize.lock().columns()
+        } else {
+            0
+        };
+        let mut cursor = self.cursor.lock();
+        let mut wrapped = Vec::new();
+        for &c in buf {
+            if width > 0 {
+                if cursor.column >= width && cursor.advances(c) {
+                    wrapped.extend_from_slice(b"\r\n");
+                    cursor.column = 0;
+                }
+                wrapped.push(c);
+            }
+            cursor.advance(c);
+        }
+        let buf = if width > 0 { &wrapped[..] } else { buf };
+        if echo {
+            self.print_echo(buf);
+        } else {
+            self.print_console(buf);
+        }
+    }
+
+    /// Print `buf` to the kernel console, keeping it on the screen buffer
+    /// and in the capture.
+    fn print_console(&self, buf: &[u8]) {
+        use core::str;
+        // the screen buffer shows chars, it takes them before translation
+        self.screen_output(buf);
+        let mut encoded = Vec::new();
+        let buf = self.encode_output(buf, &mut encoded);
+        self.capture_output(buf);
+        // we do not care the utf-8 things, we just want to print it!
+        let s = unsafe { str::from_utf8_unchecked(buf) };
+        print!("{}", s);
+    }
+
+    /// Translate `buf` to the output encoding, return the bytes to print,
+    /// in `encoded` unless it is UTF-8.
+    fn encode_output<'a>(&self, buf: &'a [u8], encoded: &'a mut Vec<u8>) -> &'a [u8] {
+        let mut encoder = self.encoder.lock();
+        if encoder.encoding() == OUTPUT_ENCODING_UTF8 {
+            return buf;
+        }
+        encoder.encode(buf, encoded);
+        encoded
+    }
+
+    /// Keep a copy of `buf` printed to the console, if capturing.
+    fn capture_output(&self, buf: &[u8]) {
+        if let Some(capture) = self.capture.lock().as_mut() {
+            let len = buf.len().min(CAPTURE_MAX - capture.bytes.len());
+            capture.bytes.extend(&buf[..len]);
+            capture.dropped += buf.len() - len;
+        }
+    }
+
+    /// Show `buf` printed to the console in the screen buffer, if keeping it.
+    fn screen_output(&self, buf: &[u8]) {
+        if let Some(screen) = self.screen.lock().as_mut() {
+            screen.feed(buf);
+        }
+    }
+
+    /// Start capturing the output printed to the console, echo included,
+    /// dropping what was captured before, or stop it.
+    pub fn set_capture(&self, enable: bool) {
+        *self.capture.lock() = if enable {
+            Some(OutputCapture::default())
+        } else {
+            None
+        };
+    }
+
+    /// Take up to `len` bytes of captured output, oldest first, with the
+    /// number of bytes dropped since the last take. `None` if not capturing.
+    pub fn take_capture(&self, len: usize) -> Option<(Vec<u8>, usize)> {
+        let mut capture = self.capture.lock();
+        let capture = capture.as_mut()?;
+        let len = len.min(capture.bytes.len());
+        let bytes = capture.bytes.drain(..len).collect();
+        let dropped = core::mem::replace(&mut capture.dropped, 0);
+        Some((bytes, dropped))
+    }
+
+    /// Give the open file description `token` of the current process
+    /// exclusive output, `Busy` if another one has it. Writes of other
+    /// processes are held back until `release_exclusive`.
+    pub fn claim_exclusive(&self, token: usize) -> Result<()> {
+        let process = match crate::process::current_thread() {
+            Some(thread) => &*thread.proc as *const _ as usize,
+            None => return Err(FsError::NotSupported),
+        };
+        let mut exclusive = self.exclusive.lock();
+        if exclusive.is_some() {
+            return Err(FsError::Busy);
+        }
+        *exclusive = Some(ExclusiveOwner { token, process });
+        Ok(())
+    }
+
+    /// End the exclusive output of `token` if it has it, printing the
+    /// writes held back meanwhile.
+    pub fn release_exclusive(&self, token: usize) {
+        {
+            let mut exclusive = self.exclusive.lock();
+            match exclusive.as_ref() {
+                Some(owner) if owner.token == token => *exclusive = None,
+                _ => return,
+            }
+        }
+        // queued behind output the rate limit holds back, never refused
+        let held = core::mem::replace(&mut *self.held.lock(), Vec::new());
+        self.queue_output(&held);
+        let dropped = self.held_dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!(
+                "tty: {} bytes written during exclusive output dropped",
+                dropped
+            );
+        }
+    }
+
+    /// Hold `buf` back if another process has exclusive output, return
+    /// whether it did. Writes outside of any thread are never held.
+    fn hold_back(&self, buf: &[u8]) -> bool {
+        let exclusive = self.exclusive.lock();
+        let owner = match exclusive.as_ref() {
+            Some(owner) => owner,
+            None => return false,
+        };
+        match crate::process::current_thread() {
+            Some(thread) if &*thread.proc as *const _ as usize != owner.process => {}
+            _ => return false,
+        }
+        let mut held = self.held.lock();
+        let len = buf.len().min(HELD_MAX - held.len());
+        held.extend_from_slice(&buf[..len]);
+        self.held_dropped
+            .fetch_add(buf.len() - len, Ordering::Relaxed);
+        true
+    }
+
+    /// Print `buf` at once, ahead of pending output, past the output rate
+    /// limit and exclusive output.
+    pub fn write_urgent(&self, buf: &[u8]) {
+        let mut filtered = Vec::with_capacity(buf.len());
+        self.sgr.lock().filter(buf, &mut filtered);
+        self.output(&filtered);
+        self.bytes_written.fetch_add(buf.len(), Ordering::Relaxed);
+    }
+
+    /// Queue `buf` for output. Pending output is printed once it reaches the
+    /// flush threshold, or contains a newline if autoflush is on.
+    fn write_buffered(&self, buf: &[u8]) {
+        let threshold = self.flush_threshold.load(Ordering::Relaxed);
+        let newline = self.autoflush.load(Ordering::Relaxed) && buf.contains(&b'\n');
+        let mut pending = self.pending.lock();
+        pending.extend_from_slice(buf);
+        if newline || pending.len() >= threshold {
+            self.print_pending(&mut pending);
+        }
+    }
+
+    /// Queue `buf` as written for output, as a hex dump if that mode is on,
+    /// see `write_buffered`.
+    fn queue_output(&self, buf: &[u8]) {
+        if self.hexdump.load(Ordering::Relaxed) {
+            // the bytes as written, before any filtering
+            let offset = self.hexdump_offset.fetch_add(buf.len(), Ordering::Relaxed);
+            let mut dump = String::new();
+            hex_dump(offset, buf, &mut dump);
+            self.write_buffered(dump.as_bytes());
+            return;
+        }
+        let mut sgr = self.sgr.lock();
+        let mut filtered = Vec::with_capacity(buf.len());
+        sgr.filter(buf, &mut filtered);
+        if self.line_stamps.load(Ordering::Relaxed) {
+            let mut stamped = Vec::with_capacity(filtered.len());
+            self.stamp_lines(&filtered, &mut stamped);
+            filtered = stamped;
+        } else if let Some(&last) = filtered.last() {
+            self.line_start.store(last == b'\n', Ordering::Relaxed);
+        }
+        self.write_buffered(&filtered);
+    }
+
+    /// Copy `buf` to `out`, prefixing each line with the current time in
+    /// microseconds like `[    1.234567] `. The prefix is written with the
+    /// first byte of a line, so a line split across writes gets only one.
+    fn stamp_lines(&self, buf: &[u8], out: &mut Vec<u8>) {
+        let mut line_start = self.line_start.load(Ordering::Relaxed);
+        for &c in buf {
+            if line_start {
+                let now = crate::arch::timer::timer_now();
+                let stamp = format!("[{:5}.{:06}] ", now.as_secs(), now.subsec_micros());
+                out.extend_from_slice(stamp.as_bytes());
+            }
+            out.push(c);
+            line_start = c == b'\n';
+        }
+        self.line_start.store(line_start, Ordering::Relaxed);
+    }
+
+    /// Restore the default attributes if written output left any set.
+    fn reset_attributes(&self) {
+        if let Some(reset) = self.sgr.lock().reset() {
+            self.write_buffered(reset);
+            self.flush_output();
+        }
+    }
+
+    /// Print all pending output.
+    pub fn flush_output(&self) {
+        self.print_pending(&mut self.pending.lock());
+    }
+
+    /// Print pending output, as much as the output rate limit allows now.
+    /// The rest stays pending, and `rate_held` is set.
+    fn print_pending(&self, pending: &mut Vec<u8>) {
+        // each chunk is printed under the console lock, so short writes
+        // never interleave while long ones may
+        let atomic_write_size = self.atomic_write_size.load(Ordering::Relaxed);
+        let mut out_rate = self.out_rate.lock();
+        let mut printed = 0;
+        for chunk in pending.chunks(atomic_write_size) {
+            // a rate limited chunk goes out in pieces as the limit allows
+            let len = out_rate.take(chunk.len());
+            if len > 0 {
+                self.output(&chunk[..len]);
+            }
+            printed += len;
+            if len < chunk.len() {
+                break;
+            }
+        }
+        drop(out_rate);
+        self.bytes_written.fetch_add(printed, Ordering::Relaxed);
+        pending.drain(..printed);
+        self.rate_held.store(!pending.is_empty(), Ordering::Relaxed);
+    }
+
+    /// Print output held back by the output rate limit as it now allows.
+    /// Called from the timer interrupt, a writer holding the output prints
+    /// it by itself.
+    pub fn release_output(&self) {
+        if !self.rate_held.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(mut pending) = self.pending.try_lock() {
+            self.print_pending(&mut pending);
+        }
+    }
+
+    /// Time until a write may succeed while output is held back by the
+    /// output rate limit, `None` if none is.
+    pub fn output_wait(&self) -> Option<Duration> {
+        if !self.rate_held.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.out_rate.lock().wait_time())
+    }
+
+    /// Send `signal` to every process in the foreground process group.
+    fn signal_foreground(&self, signal: Signal) {
+        let pgid = *self.foreground_pgid.lock();
+        signal_group(pgid, signal);
+    }
+
+    /// Copy console output to the end of `file` from now on, or stop if
+    /// `None`, for TIOCSLOGFD. The syscall layer resolves the fd.
+    pub fn set_log_file(&self, file: Option<Arc<dyn INode>>) -> Result<()> {
+        if let Some(file) = &file {
+            // copying to a device could loop back to the console
+            if file.metadata()?.type_ != FileType::File {
+                return Err(FsError::InvalidParam);
+            }
+        }
+        crate::logging::set_log_file(file)
+    }
+
+    /// Stop a read by the process group `pgid` outside the foreground
+    /// group, sending it SIGTTIN. The syscall layer checks before reading,
+    /// with the pgid read while it held the process lock.
+    pub fn check_foreground(&self, pgid: Pgid) -> Result<()> {
+        if pgid != *self.foreground_pgid.lock() {
+            signal_group(pgid, Signal::SIGTTIN);
+            return Err(FsError::Interrupted);
+        }
+        Ok(())
+    }
+
+    /// Move available input into `buf` under a single lock acquisition,
+    /// return the number of bytes read, `None` if there is none.
+    /// In canonical mode or with `line_read` at most one line is returned,
+    /// in canonical mode also ended by the VEOL or VEOL2 char.
+    /// A read reaching an end of file returns `Some(0)`, taking the end of
+    /// file.
+    pub fn pop_into(&self, buf: &mut [u8]) -> Option<usize> {
+        let termios = *self.termios.lock();
+        let (eols, count) = read_delims(&termios, self.line_read.load(Ordering::Relaxed));
+        let delims = &eols[..count];
+        let mut input = self.input.lock();
+        let mut limit = buf.len();
+        {
+            let mut eofs = self.eofs.lock();
+            if let Some(&eof) = eofs.front() {
+                let next = next_seq(&input, self.input_seq.load(Ordering::SeqCst));
+                match eof_read_limit(eof, next, limit) {
+                    Some(len) => limit = len,
+                    None => {
+                        eofs.pop_front();
+                        drop(eofs);
+                        self.input_changed(&input);
+                        return Some(0);
+                    }
+                }
+            }
+        }
+        let len = input.pop_into(&mut buf[..limit], delims);
+        self.input_changed(&input);
+        match len {
+            0 => None,
+            len => Some(len),
+        }
+    }
+
+    /// Bring `readable` in step with `input`, which must be locked.
+    fn input_changed(&self, input: &ByteQueue) {
+        let eof = !self.eofs.lock().is_empty();
+        self.readable
+            .store(input_readable(input, eof), Ordering::Relaxed);
+    }
+
+    /// Return the next input byte without consuming it, `None` if there is
+    /// none. Readiness events are left as they are.
+    pub fn peek(&self) -> Option<u8> {
+        self.input.lock().front()
+    }
+
+    /// Take the next key from the input, `Again` if there is none yet.
+    /// A lone ESC is held for `ESC_TIMEOUT` in case a sequence follows.
+    fn read_key(&self) -> Result<KeyEvent> {
+        let mut input = self.input.lock();
+        let mut bytes = [0u8; CSI_MAX];
+        let len = input.peek(&mut bytes);
+        if len == 0 {
+            return Err(FsError::Again);
+        }
+        let (event, used) = match keys::decode(&bytes[..len]) {
+            Decoded::Key(event, used) => (event, used),
+            Decoded::Incomplete => {
+                let now = crate::arch::timer::timer_now();
+                let waited = now.checked_sub(*self.last_input.lock());
+                if waited.unwrap_or_default() < ESC_TIMEOUT {
+                    return Err(FsError::Again);
+                }
+                let escape = KeyEvent {
+                    key: 0x1b,
+                    modifiers: 0,
+                };
+                (escape, 1)
+            }
+        };
+        input.pop_into(&mut bytes[..used], &[]);
+        self.input_changed(&input);
+        Ok(event)
+    }
+
+    /// Sequence number of the next byte a read returns.
+    ///
+    /// Every byte made readable or dropped takes the next number, so a gap
+    /// means lost input. Bytes consumed by line editing take none.
+    pub fn next_input_seq(&self) -> usize {
+        let input = self.input.lock();
+        next_seq(&input, self.input_seq.load(Ordering::SeqCst))
+    }
+
+    /// Queue an event for TIOCGEVENT if the event mode is on, waking
+    /// readers and pollers.
+    fn push_event(&self, kind: u32, data: [u32; 2]) {
+        if !self.event_mode.load(Ordering::Relaxed) {
+            return;
+        }
+        {
+            let mut events = self.events.lock();
+            if events.len() >= EVENTS_MAX {
+                events.pop_front();
+            }
+            events.push_back(TtyEvent { kind, data });
+        }
+        // waiters check for events under the input lock, so this wakes
+        // every one which missed it
+        self.input.lock().wake_all();
+    }
+
+    fn has_events(&self) -> bool {
+        self.event_mode.load(Ordering::Relaxed) && !self.events.lock().is_empty()
+    }
+
+    /// Take the next event, `Again` if there is none yet.
+    fn read_event(&self) -> Result<TtyEvent> {
+        if let Some(event) = self.events.lock().pop_front() {
+            return Ok(event);
+        }
+        let key = self.read_key()?;
+        Ok(TtyEvent {
+            kind: TTY_EVENT_KEY,
+            data: [key.key, key.modifiers],
+        })
+    }
+
+    /// Number of input bytes a read can return right now. Input is kept as
+    /// bytes, so a multibyte character counts its whole UTF-8 encoding.
+    pub fn available(&self) -> usize {
+        self.input.lock().len()
+    }
+
+    pub fn can_read(&self) -> bool {
+        self.available() > 0
+    }
+
+    /// Whether all written output has been transmitted, printing pending
+    /// output first as the output rate limit allows. Devices can not
+    /// sleep: the requests waiting for it fail with `Again` until it is,
+    /// and sys_ioctl sleeps between tries.
+    pub fn output_drained(&self) -> bool {
+        let mut pending = self.pending.lock();
+        self.print_pending(&mut pending);
+        // the last bytes may still be in the transmit FIFO of the port
+        pending.is_empty() && serial::console_drained()
+    }
+
+    /// Make reads blocked right now fail with `Interrupted`, leaving the
+    /// input in place. Later reads are not affected.
+    pub fn cancel_reads(&self) {
+        self.read_generation.fetch_add(1, Ordering::SeqCst);
+        // wake every subscribed waiter, they see the new generation
+        self.input.lock().wake_all();
+    }
+
+    /// Discard all received but not yet read input.
+    pub fn flush_input(&self) {
+        let mut input = self.input.lock();
+        input.clear();
+        self.eofs.lock().clear();
+        self.input_changed(&input);
+    }
+
+    /// Keep `c` in the recording ring if recording, dropping the oldest
+    /// byte when it is full.
+    fn record_input(&self, c: u8) {
+        if self.record_max.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut record = self.record.lock();
+        let max = self.record_max.load(Ordering::Relaxed);
+        if max == 0 {
+            return;
+        }
+        while record.len() >= max {
+            record.pop_front();
+        }
+        record.push_back(c);
+    }
+
+    /// Record received bytes into a ring of `size` bytes from now on, at
+    /// most `RECORD_MAX`, dropping what was recorded before. `size` 0 stops
+    /// recording and keeps the recorded bytes.
+    pub fn set_recording(&self, size: usize) {
+        let mut record = self.record.lock();
+        if size > 0 {
+            record.clear();
+        }
+        self.record_max
+            .store(size.min(RECORD_MAX), Ordering::Relaxed);
+    }
+
+    /// Copy of the recorded bytes as they were received, oldest first.
+    pub fn recorded(&self) -> Vec<u8> {
+        self.record.lock().iter().cloned().collect()
+    }
+
+    /// Copy of the unread input, for replaying it later.
+    #[cfg(debug_assertions)]
+    pub fn save_input(&self) -> Vec<u8> {
+        self.input.lock().to_vec()
+    }
+
+    /// Replace the unread input with `bytes`, waking readers if it is not
+    /// empty.
+    #[cfg(debug_assertions)]
+    pub fn load_input(&self, bytes: &[u8]) {
+        let mut input = self.input.lock();
+        input.replace(bytes);
+        self.eofs.lock().clear();
+        self.input_changed(&input);
+    }
+
+    /// Run the line discipline of a scratch tty on a marker line, return
+    /// whether it became readable and was echoed unchanged. Touches no
+    /// hardware and no state of the real ttys.
+    #[cfg(debug_assertions)]
+    pub fn self_test() -> bool {
+        const MARKER: &[u8] = b"rcore tty self test\n";
+        let tty = TtyINode::default();
+        // an echo delay no tick ends keeps the echo queued, off the console.
+        // The scratch tty is not released by the timer
+        *tty.echo_delay.lock() = Duration::from_secs(1 << 32);
+        for &c in MARKER {
+            tty.push(c);
+        }
+        let mut input = [0u8; MARKER.len()];
+        let len = tty.input.lock().pop_into(&mut input, &[]);
+        let echoed: Vec<u8> = tty.paced_echo.lock().iter().cloned().collect();
+        &input[..len] == MARKER && &echoed[..] == MARKER
+    }
+
+    /// Replace all of termios under one lock, so a concurrent TCGETS or
+    /// received byte sees either the old or the new settings, never a mix.
+    fn set_termios(&self, termios: Termios) {
+        let mut current = self.termios.lock();
+        if checks_utf8(&current) != checks_utf8(&termios) {
+            // a sequence started before would be misread. Reset before the
+            // new settings are seen, so no byte checked under them finds it
+            self.utf8.lock().reset();
+        }
+        *current = termios;
+        drop(current);
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        info!("set lfags: {:?}", lflag);
+    }
+}
+
+/// What overflow policy `policy` does with input arriving while the input
+/// buffer is full: whether the input counts as taken, dropped, and whether
+/// the bell rings. Refused input is left to its producer.
+fn overflow_action(policy: usize) -> (bool, bool) {
+    match policy {
+        OVERFLOW_ERROR => (false, false),
+        OVERFLOW_DROP => (true, false),
+        _ => (true, true),
+    }
+}
+
+/// Sequence number of the next byte read from `input`, `input_seq` when
+/// none is queued.
+fn next_seq(input: &ByteQueue, input_seq: usize) -> usize {
+    input.next_seq().unwrap_or(input_seq)
+}
+
+/// The input capacity set for `max` bytes with `held` bytes of input held,
+/// see `set_input_max`.
+fn clamp_input_max(max: usize, held: usize) -> Result<usize> {
+    let max = max.min(INPUT_MAX_LIMIT);
+    if held > max {
+        return Err(FsError::Busy);
+    }
+    Ok(max)
+}
+
+/// What line limit policy `policy` does with a char typed into a pending
+/// canonical line of `len` bytes with the limit `max_canon`: whether the
+/// char is refused with the bell, and whether the line is delivered once
+/// the char is taken.
+fn line_limit_action(policy: usize, len: usize, max_canon: usize) -> (bool, bool) {
+    let deliver = policy == CANON_FULL_DELIVER;
+    (
+        !deliver && len >= max_canon,
+        deliver && len + 1 >= max_canon,
+    )
+}
+
+/// Whether input is checked as UTF-8 under `termios`, only in canonical
+/// mode: raw mode transfers must get their bytes as sent.
+fn checks_utf8(termios: &Termios) -> bool {
+    let iflag = InputModes::from_bits_truncate(termios.iflag);
+    let lflag = LocalModes::from_bits_truncate(termios.lflag);
+    iflag.contains(InputModes::IUTF8) && lflag.contains(LocalModes::ICANON)
+}
+
+/// How many of `len` bytes a read starting at the input sequence number
+/// `next` takes with an end of file at `eof`, `None` if all input typed
+/// before the end of file is read and the read returns it.
+fn eof_read_limit(eof: usize, next: usize, len: usize) -> Option<usize> {
+    if eof <= next {
+        return None;
+    }
+    // a read does not go past it
+    Some(len.min(eof - next))
+}
+
+/// The bytes a read stops after under `termios`, as this many first bytes
+/// of the array: newline and the VEOL and VEOL2 chars in canonical mode,
+/// only newline in raw mode with `line_read`, none otherwise.
+fn read_delims(termios: &Termios, line_read: bool) -> ([u8; 3], usize) {
+    // a disabled char stands in as another newline
+    let eol = |index: usize| match termios.cc[index] {
+        POSIX_VDISABLE => b'\n',
+        c => c,
+    };
+    let eols = [b'\n', eol(VEOL), eol(VEOL2)];
+    let lflag = LocalModes::from_bits_truncate(termios.lflag);
+    let count = if lflag.contains(LocalModes::ICANON) {
+        eols.len()
+    } else if line_read {
+        1
+    } else {
+        0
+    };
+    (eols, count)
+}
+
+/// What `newline_fold` makes of the received byte `c`, `after_cr` if the
+/// byte before was `\r`: `\r` becomes `\n`, and `None` drops the `\n` of
+/// `\r\n`, as the `\r` before already ended the line.
+fn fold_newline(c: u8, after_cr: bool) -> Option<u8> {
+    match c {
+        b'\n' if after_cr => None,
+        b'\r' => Some(b'\n'),
+        c => Some(c),
+    }
+}
+
+/// Whether a read of `input` returns without waiting, also with none to
+/// read if an end of file is pending as `eof`.
+fn input_readable(input: &ByteQueue, eof: bool) -> bool {
+    !input.is_empty() || eof
+}
+
+impl DeviceState for TtyINode {
+    fn dump_state(&self, out: &mut dyn Write) -> core::fmt::Result {
+        match self.input.try_lock() {
+            Some(input) => write!(
+                out,
+                "input {}/{} bytes",
+                input.len(),
+                self.input_max.load(Ordering::Relaxed)
+            )?,
+            None => write!(out, "input locked")?,
+        }
+        match self.line.try_lock() {
+            Some(line) => write!(out, ", line {} bytes", line.len())?,
+            None => write!(out, ", line locked")?,
+        }
+        match self.pending.try_lock() {
+            Some(pending) => write!(out, ", output {} bytes pending", pending.len())?,
+            None => write!(out, ", output locked")?,
+        }
+        match self.termios.try_lock() {
+            Some(termios) => write!(
+                out,
+                ", iflag {:#o} oflag {:#o} lflag {:#o}",
+                termios.iflag, termios.oflag, termios.lflag
+            ),
+            None => write!(out, ", termios locked"),
+        }
+    }
+}
+
+impl INode for TtyINode {
+    /// Read bytes at `offset` into `buf`, return the number of bytes read.
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() == 0 {
+            return Ok(0);
+        }
+        // show a prompt written without newline before waiting for input
+        self.flush_output();
+        // Ok(0) is the end of file, then reads wait for input again
+        self.pop_into(buf).ok_or(FsError::Again)
+    }
+
+    /// Write bytes at `offset` from `buf`, return the number of bytes written.
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.hold_back(buf) {
+            return Ok(buf.len());
+        }
+        // output held back by the rate limit goes first, blocking writers
+        // sleep until it may, see `output_wait`
+        if self.rate_held.load(Ordering::Relaxed) {
+            let mut pending = self.pending.lock();
+            self.print_pending(&mut pending);
+            if self.rate_held.load(Ordering::Relaxed) {
+                return Err(FsError::Again);
+            }
+        }
+        self.queue_output(buf);
+        Ok(buf.len())
+    }
+
+    /// Poll the events, return a bitmap of events.
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            // may be stale by a push or read in progress, waiters
+            // recheck under the lock
+            read: self.readable.load(Ordering::Relaxed) || self.has_events(),
+            write: true,
+            error: false,
+        })
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct SerialFuture<'a> {
+            tty: &'a TtyINode,
+            generation: usize,
+        };
+
+        impl<'a> Future for SerialFuture<'a> {
+            type Output = Result<PollStatus>;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                // check and subscribe under one lock to not miss a push or
+                // a cancel
+                let mut input = self.tty.input.lock();
+                if self.tty.read_generation.load(Ordering::SeqCst) != self.generation {
+                    return Poll::Ready(Err(FsError::Interrupted));
+                }
+                let eof = !self.tty.eofs.lock().is_empty();
+                if !input.is_empty() || eof || self.tty.has_events() {
+                    drop(input);
+                    return Poll::Ready(self.tty.poll());
+                }
+                input.subscribe(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+
+        impl<'a> Drop for SerialFuture<'a> {
+            fn drop(&mut self) {
+                self.tty.waiting.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        Box::pin(SerialFuture {
+            tty: self,
+            generation: self.read_generation.load(Ordering::SeqCst),
+        })
+    }
+
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        let cmd = cmd as usize;
+        match cmd {
+            TIOCGPGRP => {
+                let argp = data as *mut i32; // pid_t
+                unsafe { *argp = *self.foreground_pgid.lock() };
+                Ok(0)
+            }
+            TIOCSPGRP => {
+                let fpgid = unsafe { *(data as *const i32) };
+                *self.foreground_pgid.lock() = fpgid;
+                // the previous job may have died leaving colors set
+                self.reset_attributes();
+                info!("tty: set foreground process group to {}", fpgid);
+                Ok(0)
+            }
+            TIOCGWINSZ => {
+                let winsize = data as *mut Winsize;
+                unsafe {
+                    *winsize = *self.winsize.lock();
+                }
+                Ok(0)
+            }
+            TIOCSWINSZ => {
+                let winsize = unsafe { *(data as *const Winsize) };
+                *self.winsize.lock() = winsize;
+                if let Some(screen) = self.screen.lock().as_mut() {
+                    screen.resize(&winsize);
+                }
+                let size = [winsize.rows() as u32, winsize.columns() as u32];
+                self.push_event(TTY_EVENT_RESIZE, size);
+                Ok(0)
+            }
+            TCGETS => {
+                let termois = data as *mut Termios;
+                // one copy, the log shows the settings returned
+                let termios = *self.termios.lock();
+                unsafe {
+                    *termois = termios;
+                }
+                let lflag = LocalModes::from_bits_truncate(termios.lflag);
+                info!("get lfags: {:?}", lflag);
+                Ok(0)
+            }
+            TCSETS => {
+                let termois = unsafe { *(data as *const Termios) };
+                self.set_termios(termois);
+                Ok(0)
+            }
+            TCSETSW => {
+                let termois = unsafe { *(data as *const Termios) };
+                if !self.output_drained() {
+                    return Err(FsError::Again);
+                }
+                self.set_termios(termois);
+                Ok(0)
+            }
+            TCSETSF => {
+                let termois = unsafe { *(data as *const Termios) };
+                if !self.output_drained() {
+                    return Err(FsError::Again);
+                }
+                self.flush_input();
+                self.set_termios(termois);
+                Ok(0)
+            }
+            TIOCGATOMIC => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.atomic_write_size.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSATOMIC => {
+                let size = unsafe { *(data as *const i32) };
+                if size <= 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.atomic_write_size
+                    .store(size as usize, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGFLUSHSZ => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.flush_threshold.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSFLUSHSZ => {
+                let size = unsafe { *(data as *const i32) };
+                if size < 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.flush_threshold.store(size as usize, Ordering::Relaxed);
+                self.flush_output();
+                Ok(0)
+            }
+            TIOCGAUTOFLUSH => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.autoflush.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSAUTOFLUSH => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.autoflush.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            FIONREAD => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.available() as i32 };
+                Ok(0)
+            }
+            TIOCPEEK => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.peek().map_or(-1, |c| c as i32) };
+                Ok(0)
+            }
+            TIOCGLINEEDIT => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.line_edit.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSLINEEDIT => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                if !self.line_edit.swap(enable, Ordering::Relaxed) || enable {
+                    return Ok(0);
+                }
+                // edits go to the end of the line again
+                self.edit_escape.lock().clear();
+                let termios = *self.termios.lock();
+                let lflag = LocalModes::from_bits_truncate(termios.lflag);
+                self.edit_key(KEY_END, &termios, lflag);
+                Ok(0)
+            }
+            TIOCGKEY => {
+                let event = self.read_key()?;
+                unsafe { *(data as *mut KeyEvent) = event };
+                Ok(0)
+            }
+            TIOCGCONSPORT => {
+                let argp = data as *mut i32;
+                unsafe { *argp = serial::console_port() as i32 };
+                Ok(0)
+            }
+            TIOCSCONSPORT => {
+                // buffered input and output stay, output continues on the new port
+                let port = unsafe { *(data as *const i32) };
+                if port < 0 || !serial::set_console_port(port as usize) {
+                    return Err(FsError::InvalidParam);
+                }
+                info!("tty: console moved to serial port {}", port);
+                Ok(0)
+            }
+            TIOCSBAUD => {
+                let baud = unsafe { *(data as *const i32) };
+                if baud <= 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                if !self.output_drained() {
+                    return Err(FsError::Again);
+                }
+                let actual = serial::console_set_baud(baud as u32).ok_or(FsError::InvalidParam)?;
+                info!("tty: console baud rate set to {}", actual);
+                Ok(actual as usize)
+            }
+            TIOCGSTATS => {
+                let argp = data as *mut TtyStats;
+                let stats = TtyStats {
+                    bytes_written: self.bytes_written.load(Ordering::Relaxed) as u64,
+                };
+                unsafe { *argp = stats };
+                Ok(0)
+            }
+            TIOCGOUTRATE => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.out_rate.lock().rate() as i32 };
+                Ok(0)
+            }
+            TIOCSOUTRATE => {
+                let rate = unsafe { *(data as *const i32) };
+                if rate < 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.out_rate.lock().set_rate(rate as usize);
+                Ok(0)
+            }
+            TIOCZSTATS => {
+                self.bytes_written.store(0, Ordering::Relaxed);
+                Ok(0)
+            }
+            #[cfg(debug_assertions)]
+            TIOCSELFTEST => {
+                let mut passed = 0;
+                if TtyINode::self_test() {
+                    passed |= SELFTEST_CONSOLE;
+                }
+                unsafe { *(data as *mut i32) = passed };
+                Ok(0)
+            }
+            #[cfg(debug_assertions)]
+            TIOCGINPUT => {
+                // sys_ioctl checked the buffer
+                let arg = unsafe { &mut *(data as *mut TtyInput) };
+                let input = self.save_input();
+                if input.len() > arg.len {
+                    return Err(FsError::InvalidParam);
+                }
+                let user_buf =
+                    unsafe { core::slice::from_raw_parts_mut(arg.buf as *mut u8, input.len()) };
+                user_buf.copy_from_slice(&input);
+                arg.len = input.len();
+                Ok(0)
+            }
+            #[cfg(debug_assertions)]
+            TIOCSINPUT => {
+                // sys_ioctl checked the buffer, copy it in first, loading
+                // holds the input
+                let arg = unsafe { *(data as *const TtyInput) };
+                let input = unsafe { core::slice::from_raw_parts(arg.buf as *const u8, arg.len) };
+                self.load_input(&input.to_vec());
+                Ok(0)
+            }
+            TIOCURGENT => {
+                // sys_ioctl checked the buffer, copy it in first, writing
+                // holds the output
+                let arg = unsafe { *(data as *const TtyInput) };
+                let buf = unsafe { core::slice::from_raw_parts(arg.buf as *const u8, arg.len) };
+                self.write_urgent(&buf.to_vec());
+                Ok(0)
+            }
+            TIOCSRECORD => {
+                let size = unsafe { *(data as *const i32) };
+                if size < 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.set_recording(size as usize);
+                Ok(0)
+            }
+            TIOCGRECORD => {
+                // sys_ioctl checked the buffer
+                let arg = unsafe { &mut *(data as *mut TtyInput) };
+                let recorded = self.recorded();
+                let len = recorded.len().min(arg.len);
+                let user_buf = unsafe { core::slice::from_raw_parts_mut(arg.buf as *mut u8, len) };
+                // the newest bytes, if they do not all fit
+                user_buf.copy_from_slice(&recorded[recorded.len() - len..]);
+                arg.len = len;
+                Ok(0)
+            }
+            TIOCSCAPTURE => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.set_capture(enable);
+                Ok(0)
+            }
+            TIOCGCAPTURE => {
+                // sys_ioctl checked the buffer before this takes the
+                // output, so no output is lost
+                let arg = unsafe { &mut *(data as *mut TtyInput) };
+                let (bytes, dropped) = self.take_capture(arg.len).ok_or(FsError::InvalidParam)?;
+                let user_buf =
+                    unsafe { core::slice::from_raw_parts_mut(arg.buf as *mut u8, bytes.len()) };
+                user_buf.copy_from_slice(&bytes);
+                arg.len = bytes.len();
+                Ok(dropped)
+            }
+            TIOCSSCREEN => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                *self.screen.lock() = if enable {
+                    Some(Screen::new(&self.winsize.lock()))
+                } else {
+                    None
+                };
+                Ok(0)
+            }
+            TIOCGSCREEN => {
+                // sys_ioctl checked the cells, copy a snapshot without
+                // holding the screen, the copy may fault
+                let arg = unsafe { &mut *(data as *mut TtyScreen) };
+                let (cells, rows, columns, (row, column)) = {
+                    let screen = self.screen.lock();
+                    let screen = screen.as_ref().ok_or(FsError::InvalidParam)?;
+                    let len = arg.len.min(screen.cells().len());
+                    (
+                        screen.cells()[..len].to_vec(),
+                        screen.rows(),
+                        screen.columns(),
+                        screen.cursor(),
+                    )
+                };
+                let user_cells = unsafe {
+                    core::slice::from_raw_parts_mut(arg.cells as *mut ScreenCell, cells.len())
+                };
+                user_cells.copy_from_slice(&cells);
+                arg.len = cells.len();
+                arg.rows = rows as u32;
+                arg.columns = columns as u32;
+                arg.row = row as u32;
+                arg.column = column as u32;
+                Ok(0)
+            }
+            TIOCGNLFOLD => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.newline_fold.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSNLFOLD => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.after_cr.store(false, Ordering::Relaxed);
+                self.newline_fold.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGOUTENC => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.encoder.lock().encoding() as i32 };
+                Ok(0)
+            }
+            TIOCSOUTENC => {
+                let encoding = unsafe { *(data as *const i32) } as usize;
+                match encoding {
+                    OUTPUT_ENCODING_UTF8 | OUTPUT_ENCODING_CP437 => {
+                        self.encoder.lock().set_encoding(encoding);
+                        Ok(0)
+                    }
+                    _ => Err(FsError::InvalidParam),
+                }
+            }
+            TIOCGUTF8ERR => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.utf8.lock().policy() as i32 };
+                Ok(0)
+            }
+            TIOCSUTF8ERR => {
+                let policy = unsafe { *(data as *const i32) } as usize;
+                match policy {
+                    UTF8_ERR_REPLACE | UTF8_ERR_SKIP | UTF8_ERR_RESYNC => {
+                        self.utf8.lock().set_policy(policy);
+                        Ok(0)
+                    }
+                    _ => Err(FsError::InvalidParam),
+                }
+            }
+            TIOCGLINEREAD => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.line_read.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSLINEREAD => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.line_read.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGLINESTAMP => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.line_stamps.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSLINESTAMP => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.line_stamps.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGWRAP => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.wrap.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSWRAP => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.wrap.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGOVERFLOW => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.overflow.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSOVERFLOW => {
+                let policy = unsafe { *(data as *const i32) } as usize;
+                match policy {
+                    OVERFLOW_BELL | OVERFLOW_DROP | OVERFLOW_ERROR => {
+                        self.overflow.store(policy, Ordering::Relaxed);
+                        Ok(0)
+                    }
+                    _ => Err(FsError::InvalidParam),
+                }
+            }
+            TIOCGINPUTMAX => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.input_max.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSINPUTMAX => {
+                let max = unsafe { *(data as *const i32) };
+                if max <= 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.set_input_max(max as usize)?;
+                Ok(0)
+            }
+            TIOCGECHODELAY => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.echo_delay.lock().as_micros() as i32 };
+                Ok(0)
+            }
+            TIOCSECHODELAY => {
+                let micros = unsafe { *(data as *const i32) };
+                let delay = Duration::from_micros(micros as u64);
+                if micros < 0 || delay > ECHO_DELAY_MAX {
+                    return Err(FsError::InvalidParam);
+                }
+                *self.echo_delay.lock() = delay;
+                Ok(0)
+            }
+            TIOCGHEXDUMP => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.hexdump.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCUTF8RESET => {
+                self.utf8.lock().reset();
+                Ok(0)
+            }
+            TIOCDRAIN => {
+                if !self.output_drained() {
+                    return Err(FsError::Again);
+                }
+                Ok(0)
+            }
+            TIOCSHEXDUMP => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                if enable && !self.hexdump.load(Ordering::Relaxed) {
+                    self.hexdump_offset.store(0, Ordering::Relaxed);
+                }
+                self.hexdump.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGEVMODE => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.event_mode.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSEVMODE => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.event_mode.store(enable, Ordering::Relaxed);
+                if !enable {
+                    self.events.lock().clear();
+                }
+                Ok(0)
+            }
+            TIOCGEVENT => {
+                let event = self.read_event()?;
+                unsafe { *(data as *mut TtyEvent) = event };
+                Ok(0)
+            }
+            TIOCGINSEQ => {
+                let argp = data as *mut u64;
+                unsafe { *argp = self.next_input_seq() as u64 };
+                Ok(0)
+            }
+            TIOCGDROPS => {
+                let argp = data as *mut u64;
+                unsafe { *argp = self.drops.load(Ordering::Relaxed) as u64 };
+                Ok(0)
+            }
+            TIOCZDROPS => {
+                self.drops.store(0, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCCANCELREADS => {
+                self.cancel_reads();
+                Ok(0)
+            }
+            TIOCGTYPEAHEAD => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.type_ahead.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSTYPEAHEAD => {
+                let enable = unsafe { *(data as *const i32) } != 0;
+                self.type_ahead.store(enable, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGMAXCANON => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.max_canon.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSMAXCANON => {
+                let max_canon = unsafe { *(data as *const i32) };
+                if max_canon <= 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.max_canon.store(max_canon as usize, Ordering::Relaxed);
+                Ok(0)
+            }
+            TIOCGCANONFULL => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.canon_full.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            TIOCSCANONFULL => {
+                let policy = unsafe { *(data as *const i32) } as usize;
+                match policy {
+                    CANON_FULL_BELL | CANON_FULL_DELIVER => {
+                        self.canon_full.store(policy, Ordering::Relaxed);
+                        Ok(0)
+                    }
+                    _ => Err(FsError::InvalidParam),
+                }
+            }
+            TIOCGSTRIPSGR => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.sgr.lock().strip() as i32 };
+                Ok(0)
+            }
+            TIOCSSTRIPSGR => {
+                let strip = unsafe { *(data as *const i32) } != 0;
+                if strip {
+                    self.reset_attributes();
+                }
+                self.sgr.lock().set_strip(strip);
+                Ok(0)
+            }
+            _ => Err(NotSupported),
+        }
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        self.flush_output();
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.flush_output();
+        Ok(())
+    }
+
+    /// Get metadata of the INode
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 13,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(5, 0),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_policies_at_capacity() {
+        assert_eq!(overflow_action(OVERFLOW_ERROR), (false, false));
+        assert_eq!(overflow_action(OVERFLOW_DROP), (true, false));
+        assert_eq!(overflow_action(OVERFLOW_BELL), (true, true));
+    }
+
+    #[test]
+    fn readable_follows_pushes_and_pops() {
+        let mut input = ByteQueue::default();
+        let mut buf = [0u8; 2];
+        assert!(!input_readable(&input, false));
+        input.push(b"abc");
+        assert!(input_readable(&input, false));
+        input.pop_into(&mut buf, &[]);
+        input.push(b"d");
+        input.pop_into(&mut buf, &[]);
+        assert!(!input_readable(&input, false));
+        assert!(input_readable(&input, true));
+    }
+
+    #[test]
+    fn each_line_ending_folds_to_one_newline() {
+        let fold = |bytes: &[u8]| {
+            let mut after_cr = false;
+            let mut out = Vec::new();
+            for &c in bytes {
+                out.extend(fold_newline(c, after_cr));
+                after_cr = c == b'\r';
+            }
+            out
+        };
+        assert_eq!(fold(b"a\rb\nc\r\nd"), b"a\nb\nc\nd");
+        assert_eq!(fold(b"\r\r\n\n"), b"\n\n\n");
+    }
+
+    #[test]
+    fn canonical_reads_stop_after_veol() {
+        let mut termios = Termios::default();
+        let (eols, count) = read_delims(&termios, false);
+        // both disabled by default, only newlines end lines
+        assert_eq!(&eols[..count], b"\n\n\n");
+        termios.cc[VEOL] = b';';
+        let (eols, count) = read_delims(&termios, false);
+        let mut input = ByteQueue::default();
+        input.push(b"ls;pwd\n");
+        let mut buf = [0u8; 16];
+        assert_eq!(input.pop_into(&mut buf, &eols[..count]), 3);
+        assert_eq!(&buf[..3], b"ls;");
+    }
+
+    #[test]
+    fn line_read_returns_one_line_in_raw_mode() {
+        let mut termios = Termios::default();
+        termios.lflag &= !LocalModes::ICANON.bits();
+        termios.cc[VEOL] = b';';
+        let (_, count) = read_delims(&termios, false);
+        assert_eq!(count, 0);
+        // a VEOL char only ends canonical lines
+        let (eols, count) = read_delims(&termios, true);
+        let mut input = ByteQueue::default();
+        input.push(b"a;b\nc\n");
+        let mut buf = [0u8; 16];
+        assert_eq!(input.pop_into(&mut buf, &eols[..count]), 4);
+        assert_eq!(input.pop_into(&mut buf, &eols[..count]), 2);
+    }
+
+    #[test]
+    fn reads_stop_at_an_end_of_file() {
+        // "ab" typed at 10 and 11, then VEOF
+        assert_eq!(eof_read_limit(12, 10, 16), Some(2));
+        assert_eq!(eof_read_limit(12, 11, 16), Some(1));
+        assert_eq!(eof_read_limit(12, 10, 1), Some(1));
+        assert_eq!(eof_read_limit(12, 12, 16), None);
+        // VEOF on an empty line
+        assert_eq!(eof_read_limit(12, 12, 0), None);
+    }
+
+    #[test]
+    fn line_limit_policies() {
+        assert_eq!(line_limit_action(CANON_FULL_BELL, 2, 4), (false, false));
+        assert_eq!(line_limit_action(CANON_FULL_BELL, 4, 4), (true, false));
+        assert_eq!(line_limit_action(CANON_FULL_DELIVER, 2, 4), (false, false));
+        // the char filling the line is taken and the line delivered
+        assert_eq!(line_limit_action(CANON_FULL_DELIVER, 3, 4), (false, true));
+    }
+
+    #[test]
+    fn input_max_is_clamped_and_keeps_held_input() {
+        assert!(matches!(clamp_input_max(16, 16), Ok(16)));
+        assert!(matches!(clamp_input_max(16, 17), Err(FsError::Busy)));
+        assert!(matches!(
+            clamp_input_max(usize::MAX, 0),
+            Ok(INPUT_MAX_LIMIT)
+        ));
+    }
+
+    #[test]
+    fn input_numbers_skip_dropped_bytes() {
+        // "ab" numbered 0 and 1, one byte dropped at 2, then "c" at 3
+        let mut input = ByteQueue::default();
+        input.push_numbered(b"ab", 0);
+        input.push_numbered(b"c", 3);
+        let mut buf = [0u8; 1];
+        assert_eq!(next_seq(&input, 4), 0);
+        input.pop_into(&mut buf, &[]);
+        input.pop_into(&mut buf, &[]);
+        assert_eq!(next_seq(&input, 4), 3);
+        input.pop_into(&mut buf, &[]);
+        assert_eq!(next_seq(&input, 4), 4);
+    }
+
+    #[test]
+    fn special_chars_render_as_text() {
+        let mut termios = Termios::default();
+        termios.cc[VEOL] = b';';
+        termios.cc[VEOL2] = 0xff;
+        let report = render_cc(&termios.cc);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 17);
+        assert_eq!(lines[0], "VINTR=^C");
+        assert_eq!(lines[2], "VERASE=^?");
+        assert_eq!(lines[6], "VMIN=1");
+        assert_eq!(lines[7], "VSWTC=undef");
+        assert_eq!(lines[11], "VEOL=;");
+        assert_eq!(lines[16], "VEOL2=0xff");
+    }
+}
@@ -0,0 +1,136 @@
+//! Pacing of written output to a byte rate, as a token bucket
+//!
+//! The bucket holds a tenth of a second of output, so a writer blocked
+//! behind the limit prints in short bursts instead of one long one.
+
+use crate::arch::timer::timer_now;
+use core::time::Duration;
+
+/// The bucket holds this fraction of a second of output
+const BURST_DIV: usize = 10;
+
+#[derive(Default)]
+pub struct RateLimit {
+    /// bytes per second, 0 for unlimited
+    rate: usize,
+    /// bytes which may be printed right now
+    tokens: usize,
+    /// time `tokens` was counted up to
+    refilled: Duration,
+}
+
+impl RateLimit {
+    pub fn rate(&self) -> usize {
+        self.rate
+    }
+
+    /// Limit output to `rate` bytes per second from now on, 0 for unlimited.
+    pub fn set_rate(&mut self, rate: usize) {
+        self.set_rate_at(rate, timer_now());
+    }
+
+    /// Take what may be printed now of `len` bytes, return how many, 0 if
+    /// none may yet.
+    pub fn take(&mut self, len: usize) -> usize {
+        self.take_at(len, timer_now())
+    }
+
+    /// Time until the next byte may be printed, 0 if one may now.
+    pub fn wait_time(&mut self) -> Duration {
+        self.wait_time_at(timer_now())
+    }
+
+    /// `set_rate` at the time `now`
+    fn set_rate_at(&mut self, rate: usize, now: Duration) {
+        self.rate = rate;
+        self.tokens = self.burst();
+        self.refilled = now;
+    }
+
+    /// `take` at the time `now`
+    fn take_at(&mut self, len: usize, now: Duration) -> usize {
+        if self.rate == 0 {
+            return len;
+        }
+        self.refill(now);
+        let len = len.min(self.tokens);
+        self.tokens -= len;
+        len
+    }
+
+    /// `wait_time` at the time `now`
+    fn wait_time_at(&mut self, now: Duration) -> Duration {
+        if self.rate == 0 {
+            return Duration::default();
+        }
+        self.refill(now);
+        if self.tokens > 0 {
+            return Duration::default();
+        }
+        // `refilled` is when the byte being earned started
+        let per_byte = (1_000_000 + self.rate as u64 - 1) / self.rate as u64;
+        let next = self.refilled + Duration::from_micros(per_byte);
+        next.checked_sub(now).unwrap_or_default()
+    }
+
+    fn burst(&self) -> usize {
+        (self.rate / BURST_DIV).max(1)
+    }
+
+    fn refill(&mut self, now: Duration) {
+        let elapsed = (now - self.refilled).as_micros() as u64;
+        let earned = (elapsed * self.rate as u64 / 1_000_000) as usize;
+        if earned == 0 {
+            return;
+        }
+        if self.tokens + earned >= self.burst() {
+            self.tokens = self.burst();
+            self.refilled = now;
+        } else {
+            self.tokens += earned;
+            // keep the time of the part of a byte not earned yet
+            self.refilled += Duration::from_micros(earned as u64 * 1_000_000 / self.rate as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_takes_everything() {
+        let mut limit = RateLimit::default();
+        assert_eq!(limit.take_at(1 << 20, Duration::default()), 1 << 20);
+        assert_eq!(limit.wait_time_at(Duration::default()), Duration::default());
+    }
+
+    #[test]
+    fn burst_then_wait() {
+        let mut limit = RateLimit::default();
+        let start = Duration::from_secs(1);
+        limit.set_rate_at(100, start);
+        // a tenth of a second of output goes at once
+        assert_eq!(limit.take_at(25, start), 10);
+        assert_eq!(limit.take_at(1, start), 0);
+        assert_eq!(limit.wait_time_at(start), Duration::from_millis(10));
+        assert_eq!(limit.take_at(1, start + Duration::from_millis(10)), 1);
+    }
+
+    #[test]
+    fn writing_past_the_limit_takes_the_time() {
+        let mut limit = RateLimit::default();
+        let start = Duration::from_secs(1);
+        limit.set_rate_at(100, start);
+        // write 100 bytes as a blocked writer does, sleeping when told to
+        let mut now = start;
+        let mut written = 0;
+        while written < 100 {
+            written += limit.take_at(100 - written, now);
+            now += limit.wait_time_at(now).max(Duration::from_micros(1));
+        }
+        // all but the first burst of 10 bytes are paced at 100 per second
+        assert!(now - start >= Duration::from_millis(900));
+        assert!(now - start < Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,407 @@
+//! Screen buffer keeping what the console shows as a grid of cells
+//!
+//! Printed output is interpreted as a terminal would: a char goes into the
+//! cell at the cursor with the current SGR attributes, CSI sequences move
+//! the cursor and erase. Other escape sequences are ignored. As for the
+//! tty cursor, `\n` also returns to the first column.
+
+use super::line::TAB_WIDTH;
+use crate::fs::ioctl::*;
+use alloc::vec::Vec;
+
+/// Size used while the window size is unknown
+const DEFAULT_ROWS: usize = 24;
+const DEFAULT_COLUMNS: usize = 80;
+
+/// Give up on CSI sequences with more parameter bytes than this
+const MAX_PARAMS_LEN: usize = 32;
+
+/// U+FFFD REPLACEMENT CHARACTER, shown for invalid UTF-8
+const REPLACEMENT: u32 = 0xfffd;
+
+const BLANK: ScreenCell = ScreenCell {
+    ch: b' ' as u32,
+    fg: SCREEN_COLOR_DEFAULT,
+    bg: SCREEN_COLOR_DEFAULT,
+    attrs: 0,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+pub struct Screen {
+    rows: usize,
+    columns: usize,
+    /// `rows * columns` cells, row by row
+    cells: Vec<ScreenCell>,
+    row: usize,
+    column: usize,
+    /// the last column was printed to, the next char goes to the next line
+    wrap_pending: bool,
+    /// attributes chars are printed with, `ch` is unused
+    pen: ScreenCell,
+    state: State,
+    /// parameter bytes of the CSI sequence being parsed
+    params: Vec<u8>,
+    /// code point of the unfinished UTF-8 sequence so far
+    ch: u32,
+    /// continuation bytes it still needs
+    missing: usize,
+}
+
+impl Screen {
+    /// Blank screen of the size of `winsize`, the cursor at the top left.
+    pub fn new(winsize: &Winsize) -> Self {
+        let (rows, columns) = screen_size(winsize);
+        Screen {
+            rows,
+            columns,
+            cells: vec![BLANK; rows * columns],
+            row: 0,
+            column: 0,
+            wrap_pending: false,
+            pen: BLANK,
+            state: State::Ground,
+            params: Vec::new(),
+            ch: 0,
+            missing: 0,
+        }
+    }
+
+    /// Change to the size of `winsize`, keeping the cells which still fit.
+    pub fn resize(&mut self, winsize: &Winsize) {
+        let (rows, columns) = screen_size(winsize);
+        let mut cells = vec![BLANK; rows * columns];
+        for row in 0..rows.min(self.rows) {
+            let len = columns.min(self.columns);
+            let old = &self.cells[row * self.columns..][..len];
+            cells[row * columns..][..len].copy_from_slice(old);
+        }
+        self.rows = rows;
+        self.columns = columns;
+        self.cells = cells;
+        self.row = self.row.min(rows - 1);
+        self.column = self.column.min(columns - 1);
+        self.wrap_pending = false;
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Row and column of the cursor, from 0.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    /// The cells, row by row.
+    pub fn cells(&self) -> &[ScreenCell] {
+        &self.cells
+    }
+
+    /// Interpret printed bytes. An unfinished sequence is kept until the
+    /// next call.
+    pub fn feed(&mut self, buf: &[u8]) {
+        for &c in buf {
+            match self.state {
+                State::Ground => self.feed_ground(c),
+                State::Escape if c == b'[' => {
+                    self.params.clear();
+                    self.state = State::Csi;
+                }
+                // escape sequences other than CSI are two bytes, ignored
+                State::Escape => self.state = State::Ground,
+                State::Csi if (0x40..=0x7e).contains(&c) => {
+                    self.state = State::Ground;
+                    self.csi(c);
+                }
+                State::Csi if self.params.len() >= MAX_PARAMS_LEN => self.state = State::Ground,
+                State::Csi => self.params.push(c),
+            }
+        }
+    }
+
+    fn feed_ground(&mut self, c: u8) {
+        if self.missing > 0 {
+            if (0x80..=0xbf).contains(&c) {
+                self.ch = self.ch << 6 | (c & 0x3f) as u32;
+                self.missing -= 1;
+                if self.missing == 0 {
+                    self.print(self.ch);
+                }
+                return;
+            }
+            // the sequence is cut short, `c` is looked at alone
+            self.missing = 0;
+            self.print(REPLACEMENT);
+        }
+        match c {
+            0x1b => self.state = State::Escape,
+            0..=0x1f | 0x7f => self.control(c),
+            0x20..=0x7e => self.print(c as u32),
+            0xc2..=0xdf => self.start_sequence((c & 0x1f) as u32, 1),
+            0xe0..=0xef => self.start_sequence((c & 0x0f) as u32, 2),
+            0xf0..=0xf4 => self.start_sequence((c & 0x07) as u32, 3),
+            _ => self.print(REPLACEMENT),
+        }
+    }
+
+    fn start_sequence(&mut self, bits: u32, missing: usize) {
+        self.ch = bits;
+        self.missing = missing;
+    }
+
+    /// Put `ch` into the cell at the cursor and move past it.
+    fn print(&mut self, ch: u32) {
+        if self.wrap_pending {
+            self.column = 0;
+            self.line_feed();
+        }
+        let mut cell = self.pen;
+        cell.ch = ch;
+        self.cells[self.row * self.columns + self.column] = cell;
+        if self.column + 1 < self.columns {
+            self.column += 1;
+        } else {
+            self.wrap_pending = true;
+        }
+    }
+
+    fn control(&mut self, c: u8) {
+        match c {
+            b'\n' => {
+                self.column = 0;
+                self.line_feed();
+            }
+            b'\r' => self.column = 0,
+            0x08 => self.column = self.column.saturating_sub(1),
+            b'\t' => {
+                self.column = ((self.column / TAB_WIDTH + 1) * TAB_WIDTH).min(self.columns - 1)
+            }
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor down a row, scrolling up at the bottom.
+    fn line_feed(&mut self) {
+        self.wrap_pending = false;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+        self.cells.drain(..self.columns);
+        self.cells
+            .extend(core::iter::repeat(BLANK).take(self.columns));
+    }
+
+    /// Handle a complete CSI sequence ending with `final_byte`.
+    fn csi(&mut self, final_byte: u8) {
+        // private sequences as `ESC [ ? 25 l` are not handled
+        if !self.params.iter().all(|&c| c.is_ascii_digit() || c == b';') {
+            return;
+        }
+        let params: Vec<usize> = self
+            .params
+            .split(|&c| c == b';')
+            .map(|param| {
+                param.iter().fold(0usize, |n, &c| {
+                    n.saturating_mul(10).saturating_add((c - b'0') as usize)
+                })
+            })
+            .collect();
+        // counts and positions of 0 or left out mean 1
+        let arg = |index: usize| params.get(index).cloned().unwrap_or(0).max(1);
+        let (last_row, last_column) = (self.rows - 1, self.columns - 1);
+        match final_byte {
+            b'A' => self.row = self.row.saturating_sub(arg(0)),
+            b'B' => self.row = self.row.saturating_add(arg(0)).min(last_row),
+            b'C' => self.column = self.column.saturating_add(arg(0)).min(last_column),
+            b'D' => self.column = self.column.saturating_sub(arg(0)),
+            b'E' => {
+                self.row = self.row.saturating_add(arg(0)).min(last_row);
+                self.column = 0;
+            }
+            b'F' => {
+                self.row = self.row.saturating_sub(arg(0));
+                self.column = 0;
+            }
+            b'G' => self.column = (arg(0) - 1).min(last_column),
+            b'd' => self.row = (arg(0) - 1).min(last_row),
+            b'H' | b'f' => {
+                self.row = (arg(0) - 1).min(last_row);
+                self.column = (arg(1) - 1).min(last_column);
+            }
+            b'J' => {
+                let cursor = self.row * self.columns + self.column;
+                match params[0] {
+                    0 => self.erase(cursor, self.cells.len()),
+                    1 => self.erase(0, cursor + 1),
+                    _ => self.erase(0, self.cells.len()),
+                }
+            }
+            b'K' => {
+                let start = self.row * self.columns;
+                let cursor = start + self.column;
+                match params[0] {
+                    0 => self.erase(cursor, start + self.columns),
+                    1 => self.erase(start, cursor + 1),
+                    _ => self.erase(start, start + self.columns),
+                }
+            }
+            b'm' => return self.sgr(&params),
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Blank the cells from `start` up to `end`.
+    fn erase(&mut self, start: usize, end: usize) {
+        for cell in &mut self.cells[start..end] {
+            *cell = BLANK;
+        }
+    }
+
+    /// Change the attributes of the pen as SGR `params` say.
+    fn sgr(&mut self, params: &[usize]) {
+        let mut i = 0;
+        while i < params.len() {
+            let pen = &mut self.pen;
+            match params[i] {
+                0 => *pen = BLANK,
+                1 => pen.attrs |= SCREEN_BOLD,
+                4 => pen.attrs |= SCREEN_UNDERLINE,
+                7 => pen.attrs |= SCREEN_REVERSE,
+                22 => pen.attrs &= !SCREEN_BOLD,
+                24 => pen.attrs &= !SCREEN_UNDERLINE,
+                27 => pen.attrs &= !SCREEN_REVERSE,
+                n @ 30..=37 => pen.fg = (n - 30) as u16,
+                39 => pen.fg = SCREEN_COLOR_DEFAULT,
+                n @ 40..=47 => pen.bg = (n - 40) as u16,
+                49 => pen.bg = SCREEN_COLOR_DEFAULT,
+                n @ 90..=97 => pen.fg = (n - 90 + 8) as u16,
+                n @ 100..=107 => pen.bg = (n - 100 + 8) as u16,
+                n @ 38 | n @ 48 => {
+                    // `5;n` picks one of 256 colors, `2;r;g;b` a true color
+                    // which is not kept
+                    let color = match params.get(i + 1) {
+                        Some(5) => params.get(i + 2).map(|&color| color.min(255) as u16),
+                        _ => None,
+                    };
+                    i += match params.get(i + 1) {
+                        Some(5) => 2,
+                        Some(2) => 4,
+                        _ => 0,
+                    };
+                    match color {
+                        Some(color) if n == 38 => pen.fg = color,
+                        Some(color) => pen.bg = color,
+                        None => {}
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Rows and columns of `winsize`, the defaults where it is unknown.
+fn screen_size(winsize: &Winsize) -> (usize, usize) {
+    let rows = match winsize.rows() {
+        0 => DEFAULT_ROWS,
+        rows => rows,
+    };
+    let columns = match winsize.columns() {
+        0 => DEFAULT_COLUMNS,
+        columns => columns,
+    };
+    (rows, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(screen: &Screen, row: usize, column: usize) -> ScreenCell {
+        screen.cells()[row * screen.columns() + column]
+    }
+
+    #[test]
+    fn sgr_sets_cell_attributes() {
+        let mut screen = Screen::new(&Winsize::default());
+        screen.feed(b"a\x1b[1;31mb\x1b[0mc");
+        assert_eq!(
+            cell(&screen, 0, 0),
+            ScreenCell {
+                ch: 'a' as u32,
+                ..BLANK
+            }
+        );
+        let bold_red = ScreenCell {
+            ch: 'b' as u32,
+            fg: 1,
+            attrs: SCREEN_BOLD,
+            ..BLANK
+        };
+        assert_eq!(cell(&screen, 0, 1), bold_red);
+        assert_eq!(
+            cell(&screen, 0, 2),
+            ScreenCell {
+                ch: 'c' as u32,
+                ..BLANK
+            }
+        );
+    }
+
+    #[test]
+    fn cursor_moves_place_chars() {
+        let mut screen = Screen::new(&Winsize::default());
+        screen.feed(b"\x1b[3;5Hx\x1b[2Dy\x1b[Az");
+        assert_eq!(cell(&screen, 2, 4).ch, 'x' as u32);
+        assert_eq!(cell(&screen, 2, 3).ch, 'y' as u32);
+        assert_eq!(cell(&screen, 1, 4).ch, 'z' as u32);
+        assert_eq!(screen.cursor(), (1, 5));
+    }
+
+    #[test]
+    fn erase_line_to_end() {
+        let mut screen = Screen::new(&Winsize::default());
+        screen.feed(b"hello\r\x1b[2C\x1b[K");
+        assert_eq!(cell(&screen, 0, 1).ch, 'e' as u32);
+        assert_eq!(cell(&screen, 0, 2), BLANK);
+        assert_eq!(cell(&screen, 0, 4), BLANK);
+    }
+
+    #[test]
+    fn utf8_and_256_colors() {
+        let mut screen = Screen::new(&Winsize::default());
+        screen.feed("\x1b[38;5;200;48;5;17m─".as_bytes());
+        let expected = ScreenCell {
+            ch: 0x2500,
+            fg: 200,
+            bg: 17,
+            attrs: 0,
+        };
+        assert_eq!(cell(&screen, 0, 0), expected);
+    }
+
+    #[test]
+    fn newline_at_bottom_scrolls() {
+        let mut screen = Screen::new(&Winsize::default());
+        screen.feed(b"top");
+        for _ in 0..DEFAULT_ROWS {
+            screen.feed(b"\n");
+        }
+        assert_eq!(screen.cursor(), (DEFAULT_ROWS - 1, 0));
+        assert_eq!(cell(&screen, 0, 0), BLANK);
+    }
+}
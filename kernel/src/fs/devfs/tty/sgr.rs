@@ -0,0 +1,114 @@
+//! Tracking of ANSI SGR (select graphic rendition) sequences in tty output
+//!
+//! Only CSI sequences are parsed, other escape sequences go through as is.
+
+use alloc::vec::Vec;
+
+const ESC: u8 = 0x1b;
+/// Give up parsing and forward sequences longer than this
+const MAX_SEQ_LEN: usize = 32;
+/// Restore the default attributes
+pub const SGR_RESET: &[u8] = b"\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Ground
+    }
+}
+
+#[derive(Default)]
+pub struct SgrFilter {
+    state: State,
+    /// bytes of the escape sequence being parsed
+    seq: Vec<u8>,
+    /// an attribute was set and not reset yet
+    dirty: bool,
+    /// drop SGR sequences instead of forwarding them
+    strip: bool,
+}
+
+impl SgrFilter {
+    /// Feed written bytes, append the ones to print to `out`.
+    /// An unfinished sequence is kept until the next call.
+    pub fn filter(&mut self, buf: &[u8], out: &mut Vec<u8>) {
+        for &c in buf {
+            match self.state {
+                State::Ground if c == ESC => {
+                    self.seq.push(c);
+                    self.state = State::Escape;
+                }
+                State::Ground => out.push(c),
+                State::Escape if c == b'[' => {
+                    self.seq.push(c);
+                    self.state = State::Csi;
+                }
+                State::Escape => {
+                    self.seq.push(c);
+                    self.forward(out);
+                }
+                State::Csi => {
+                    self.seq.push(c);
+                    if (0x40..=0x7e).contains(&c) {
+                        self.finish(c, out);
+                    } else if self.seq.len() >= MAX_SEQ_LEN {
+                        self.forward(out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle a complete CSI sequence ending with `final_byte`.
+    fn finish(&mut self, final_byte: u8, out: &mut Vec<u8>) {
+        if final_byte != b'm' {
+            return self.forward(out);
+        }
+        if self.strip {
+            self.seq.clear();
+            self.state = State::Ground;
+            return;
+        }
+        // skip ESC '[' and the final byte
+        let params = &self.seq[2..self.seq.len() - 1];
+        for param in params.split(|&c| c == b';') {
+            // 38 and 48 take colors as arguments, which may be 0
+            if param == b"38" || param == b"48" {
+                self.dirty = true;
+                break;
+            }
+            // an empty parameter means 0, which resets everything
+            self.dirty = !param.iter().all(|&c| c == b'0');
+        }
+        self.forward(out);
+    }
+
+    fn forward(&mut self, out: &mut Vec<u8>) {
+        out.extend(self.seq.drain(..));
+        self.state = State::Ground;
+    }
+
+    pub fn strip(&self) -> bool {
+        self.strip
+    }
+
+    pub fn set_strip(&mut self, strip: bool) {
+        self.strip = strip;
+    }
+
+    /// Return the sequence restoring the default attributes if any is set.
+    pub fn reset(&mut self) -> Option<&'static [u8]> {
+        if self.dirty {
+            self.dirty = false;
+            Some(SGR_RESET)
+        } else {
+            None
+        }
+    }
+}
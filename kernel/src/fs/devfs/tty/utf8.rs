@@ -0,0 +1,216 @@
+//! Checking of canonical mode input as UTF-8, for IUTF8
+//!
+//! Bytes of a valid sequence are held until it is complete, then passed on
+//! together. What replaces an invalid sequence depends on the `UTF8_ERR_*`
+//! policy. A sequence is invalid from the first byte which cannot continue
+//! it, that byte then starts over.
+
+use crate::fs::ioctl::*;
+
+/// Most bytes given back for one byte pushed: a replacement for the
+/// sequence it cut short, then one for itself
+pub const DECODED_MAX: usize = 6;
+
+/// U+FFFD REPLACEMENT CHARACTER
+const REPLACEMENT: [u8; 3] = [0xef, 0xbf, 0xbd];
+
+#[derive(Clone, Copy)]
+pub struct Utf8Decoder {
+    /// one of the `UTF8_ERR_*` policies
+    policy: usize,
+    /// bytes of the unfinished sequence
+    pending: [u8; 4],
+    len: usize,
+    /// length of the unfinished sequence once complete
+    need: usize,
+    /// an invalid sequence was replaced and no valid one started since
+    resyncing: bool,
+}
+
+impl Default for Utf8Decoder {
+    fn default() -> Self {
+        Utf8Decoder {
+            policy: UTF8_ERR_REPLACE,
+            pending: [0; 4],
+            len: 0,
+            need: 0,
+            resyncing: false,
+        }
+    }
+}
+
+impl Utf8Decoder {
+    pub fn policy(&self) -> usize {
+        self.policy
+    }
+
+    /// Apply `policy` from now on, forgetting the unfinished sequence.
+    pub fn set_policy(&mut self, policy: usize) {
+        self.policy = policy;
+        self.reset();
+    }
+
+    /// Forget the unfinished sequence, the next byte starts over.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.resyncing = false;
+    }
+
+    /// Feed `c`, return the number of bytes put into `out` to be taken as
+    /// input.
+    pub fn push_byte(&mut self, c: u8, out: &mut [u8; DECODED_MAX]) -> usize {
+        let mut len = 0;
+        if self.len > 0 {
+            if self.continues(c) {
+                self.pending[self.len] = c;
+                self.len += 1;
+                if self.len < self.need {
+                    return 0;
+                }
+                out[..self.len].copy_from_slice(&self.pending[..self.len]);
+                let len = self.len;
+                self.len = 0;
+                return len;
+            }
+            // the sequence is cut short, `c` is looked at alone
+            self.len = 0;
+            len += self.invalid(&mut out[len..]);
+        }
+        match sequence_len(c) {
+            Some(1) => {
+                self.resyncing = false;
+                out[len] = c;
+                len + 1
+            }
+            Some(need) => {
+                self.resyncing = false;
+                self.pending[0] = c;
+                self.len = 1;
+                self.need = need;
+                len
+            }
+            None => len + self.invalid(&mut out[len..]),
+        }
+    }
+
+    /// Whether `c` may be the next byte of the unfinished sequence.
+    /// The second byte is narrower after some lead bytes, ruling out
+    /// overlong encodings, surrogates and code points past U+10FFFF.
+    fn continues(&self, c: u8) -> bool {
+        let (low, high) = match (self.len, self.pending[0]) {
+            (1, 0xe0) => (0xa0, 0xbf),
+            (1, 0xed) => (0x80, 0x9f),
+            (1, 0xf0) => (0x90, 0xbf),
+            (1, 0xf4) => (0x80, 0x8f),
+            _ => (0x80, 0xbf),
+        };
+        (low..=high).contains(&c)
+    }
+
+    /// Put what replaces an invalid sequence into `out`, return its length.
+    fn invalid(&mut self, out: &mut [u8]) -> usize {
+        match self.policy {
+            UTF8_ERR_SKIP => 0,
+            // the rest of the garbage was replaced already
+            UTF8_ERR_RESYNC if self.resyncing => 0,
+            _ => {
+                self.resyncing = true;
+                out[..REPLACEMENT.len()].copy_from_slice(&REPLACEMENT);
+                REPLACEMENT.len()
+            }
+        }
+    }
+}
+
+/// Length of the sequence lead byte `c` starts, `None` if it starts none.
+fn sequence_len(c: u8) -> Option<usize> {
+    match c {
+        0..=0x7f => Some(1),
+        0xc2..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf4 => Some(4),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Feed `bytes` to `decoder`, return what it takes as input.
+    fn decode(decoder: &mut Utf8Decoder, bytes: &[u8]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for &c in bytes {
+            let mut out = [0; DECODED_MAX];
+            let len = decoder.push_byte(c, &mut out);
+            input.extend_from_slice(&out[..len]);
+        }
+        input
+    }
+
+    fn with_policy(policy: usize) -> Utf8Decoder {
+        let mut decoder = Utf8Decoder::default();
+        decoder.set_policy(policy);
+        decoder
+    }
+
+    #[test]
+    fn valid_sequences_pass_whole() {
+        let mut decoder = Utf8Decoder::default();
+        let mut out = [0; DECODED_MAX];
+        assert_eq!(decoder.push_byte(0xe2, &mut out), 0);
+        assert_eq!(decoder.push_byte(0x82, &mut out), 0);
+        assert_eq!(decoder.push_byte(0xac, &mut out), 3);
+        assert_eq!(&out[..3], "€".as_bytes());
+        assert_eq!(decode(&mut decoder, "aé".as_bytes()), "aé".as_bytes());
+    }
+
+    #[test]
+    fn replace_each_invalid_byte() {
+        let mut decoder = with_policy(UTF8_ERR_REPLACE);
+        assert_eq!(
+            decode(&mut decoder, b"\xff\xfeA"),
+            "\u{fffd}\u{fffd}A".as_bytes()
+        );
+    }
+
+    #[test]
+    fn cut_short_sequence_is_replaced_and_byte_kept() {
+        let mut decoder = with_policy(UTF8_ERR_REPLACE);
+        assert_eq!(decode(&mut decoder, b"\xc3A"), "\u{fffd}A".as_bytes());
+    }
+
+    #[test]
+    fn skip_drops_invalid_bytes() {
+        let mut decoder = with_policy(UTF8_ERR_SKIP);
+        assert_eq!(decode(&mut decoder, b"\xff\xc3A\x80"), b"A");
+    }
+
+    #[test]
+    fn resync_replaces_a_run_once() {
+        let mut decoder = with_policy(UTF8_ERR_RESYNC);
+        assert_eq!(
+            decode(&mut decoder, b"\xff\xfe\x80A\xff"),
+            "\u{fffd}A\u{fffd}".as_bytes()
+        );
+    }
+
+    #[test]
+    fn overlong_encoding_is_invalid() {
+        let mut decoder = with_policy(UTF8_ERR_REPLACE);
+        // 0xe0 0x80 0x80 would encode U+0000 in three bytes
+        let input = decode(&mut decoder, b"\xe0\x80\x80");
+        assert_eq!(input, "\u{fffd}\u{fffd}\u{fffd}".as_bytes());
+    }
+
+    #[test]
+    fn reset_drops_an_unfinished_sequence() {
+        let mut decoder = Utf8Decoder::default();
+        assert!(decode(&mut decoder, b"\xe2\x82").is_empty());
+        decoder.reset();
+        // without the reset this would replace the cut short sequence
+        assert_eq!(decode(&mut decoder, b"A"), b"A");
+        assert_eq!(decode(&mut decoder, "é".as_bytes()), "é".as_bytes());
+    }
+}
@@ -0,0 +1,146 @@
+//! Implement INode for the hardware watchdog, following the Linux watchdog API
+
+use super::DeviceHooks;
+use crate::arch::board::watchdog::{self, MAX_TIMEOUT_SECS};
+use crate::fs::ioctl::*;
+use core::any::Any;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use rcore_fs::vfs::*;
+
+/// Reset the board unless it is written to within the timeout
+///
+/// The first write starts the watchdog, any later write or
+/// `WDIOC_KEEPALIVE` restarts its count. Writing a `V` arms the magic close:
+/// closing the device afterwards stops the watchdog, closing it without
+/// does not, so a crashed daemon still gets the board reset.
+pub struct WatchdogINode {
+    /// in seconds
+    timeout: AtomicU32,
+    active: AtomicBool,
+    expect_close: AtomicBool,
+}
+
+impl Default for WatchdogINode {
+    fn default() -> Self {
+        WatchdogINode {
+            timeout: AtomicU32::new(MAX_TIMEOUT_SECS),
+            active: AtomicBool::new(false),
+            expect_close: AtomicBool::new(false),
+        }
+    }
+}
+
+impl WatchdogINode {
+    /// Start the watchdog or restart its count.
+    fn ping(&self) {
+        watchdog::start(self.timeout.load(Ordering::Relaxed));
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    fn stop(&self) {
+        watchdog::stop();
+        self.active.store(false, Ordering::Relaxed);
+    }
+
+    /// Stop the watchdog on magic close.
+    fn release(&self) {
+        if self.expect_close.swap(false, Ordering::Relaxed) {
+            self.stop();
+            info!("watchdog: stopped on magic close");
+        } else if self.active.load(Ordering::Relaxed) {
+            warn!("watchdog: closed unexpectedly, not stopping");
+        }
+    }
+}
+
+impl DeviceHooks for WatchdogINode {
+    fn last_close(&self) {
+        self.release();
+    }
+}
+
+impl INode for WatchdogINode {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        if !buf.is_empty() {
+            let magic = buf.contains(&b'V');
+            self.expect_close.store(magic, Ordering::Relaxed);
+            self.ping();
+        }
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            WDIOC_KEEPALIVE => {
+                self.ping();
+                Ok(0)
+            }
+            WDIOC_SETTIMEOUT => {
+                let argp = data as *mut i32;
+                let secs = unsafe { *argp };
+                if secs <= 0 || secs as u32 > MAX_TIMEOUT_SECS {
+                    return Err(FsError::InvalidParam);
+                }
+                self.timeout.store(secs as u32, Ordering::Relaxed);
+                if self.active.load(Ordering::Relaxed) {
+                    self.ping();
+                }
+                // as Linux, the timeout in effect is written back
+                unsafe { *argp = self.timeout.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            WDIOC_GETTIMEOUT => {
+                let argp = data as *mut i32;
+                unsafe { *argp = self.timeout.load(Ordering::Relaxed) as i32 };
+                Ok(0)
+            }
+            WDIOC_SETOPTIONS => {
+                let options = unsafe { *(data as *const i32) };
+                if options & WDIOS_DISABLECARD != 0 {
+                    self.stop();
+                }
+                if options & WDIOS_ENABLECARD != 0 {
+                    self.ping();
+                }
+                Ok(0)
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: 6,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o600,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(10, 130),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
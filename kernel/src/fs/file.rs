@@ -1,21 +1,102 @@
 //! File handle for process
 
+use crate::arch::timer::timer_now;
 use crate::memory::GlobalFrameAlloc;
-use crate::process::{current_thread, INodeForMap};
+use crate::process::{current_thread, INodeForMap, Pgid, Thread, Tid};
 use crate::syscall::{MmapProt, SysResult, TimeSpec};
-use alloc::{string::String, sync::Arc};
+use crate::trap::NAIVE_TIMER;
+use alloc::{boxed::Box, collections::BTreeSet, string::String, sync::Arc};
 use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+use core::time::Duration;
 
 use rcore_fs::vfs::FsError::{Interrupted, NotSupported};
 use rcore_fs::vfs::{FileType, FsError, INode, MMapArea, Metadata, PollStatus, Result};
 use rcore_memory::memory_set::handler::File;
 
+use crate::fs::devfs::{device_closed, device_opened, TtyINode};
 use crate::fs::fcntl::{O_APPEND, O_NONBLOCK};
+use crate::fs::ioctl::{
+    FIOGETEXACT, FIOGETLOWAT, FIOGETOSPACE, FIOGETRCVTIMEO, FIOSETEXACT, FIOSETLOWAT,
+    FIOSETRCVTIMEO, WAIT_EVENT_ERROR, WAIT_EVENT_READABLE,
+};
+use crate::fs::Pipe;
 use crate::sync::SpinLock as Mutex;
+use crate::sync::{EventBus, SpinNoIrqLock};
 use crate::syscall::SysError::{EAGAIN, ESPIPE};
 use bitflags::_core::cell::Cell;
 use spin::RwLock;
 
+/// How a blocking wait of `FileHandle::wait_ready` ended. When several
+/// happen together, the first one listed wins, so data which arrived is
+/// read even if the deadline passed or a signal came meanwhile.
+enum WaitOutcome {
+    /// the inode became ready, or failed
+    Ready(Result<PollStatus>),
+    /// the inode gave up the wait, as a tty on `cancel_reads`
+    Cancelled,
+    /// the deadline passed
+    TimedOut,
+    /// the current thread has a signal to handle
+    Interrupted,
+}
+
+/// How a wait ends at a poll which found the inode `ready` or not, the
+/// deadline passed if `timed_out` and a signal to handle if `signaled`.
+/// `None` if it goes on.
+fn wait_outcome(
+    ready: Poll<Result<PollStatus>>,
+    timed_out: bool,
+    signaled: bool,
+) -> Option<WaitOutcome> {
+    match ready {
+        Poll::Ready(Err(Interrupted)) => Some(WaitOutcome::Cancelled),
+        Poll::Ready(ret) => Some(WaitOutcome::Ready(ret)),
+        Poll::Pending if timed_out => Some(WaitOutcome::TimedOut),
+        Poll::Pending if signaled => Some(WaitOutcome::Interrupted),
+        Poll::Pending => None,
+    }
+}
+
+/// What a blocking read having read `total` bytes returns when its wait
+/// ended with `outcome`, `None` if it reads again.
+fn read_wait_result(total: usize, outcome: WaitOutcome) -> Option<Result<usize>> {
+    let err = match outcome {
+        WaitOutcome::Ready(Ok(_)) => return None,
+        WaitOutcome::Ready(Err(err)) => err,
+        WaitOutcome::TimedOut => FsError::Again,
+        WaitOutcome::Interrupted | WaitOutcome::Cancelled => Interrupted,
+    };
+    // interrupted or timed out after reading some, POSIX wants the partial
+    // count rather than an error
+    if total > 0 {
+        return Some(Ok(total));
+    }
+    Some(Err(err))
+}
+
+/// Threads whose last poll found an inode readable, with no read by them
+/// since. Kept per thread, so a poll by one thread sharing an open file
+/// description never makes the blocking reads of another fail.
+#[derive(Default)]
+struct PolledReaders(BTreeSet<Tid>);
+
+impl PolledReaders {
+    /// Note a poll by `tid` found the inode readable.
+    fn polled(&mut self, tid: Tid) {
+        self.0.insert(tid);
+    }
+
+    /// Whether `tid` polled the inode readable since its last read, a new
+    /// read of it starts.
+    fn take(&mut self, tid: Tid) -> bool {
+        self.0.remove(&tid)
+    }
+}
+
 enum Flock {
     None = 0,
     Shared = 1,
@@ -26,18 +107,64 @@ struct OpenFileDescription {
     offset: u64,
     options: OpenOptions,
     flock: Flock,
+    /// blocking reads wait until this many bytes are read, EOF or error
+    read_lowat: usize,
+    /// blocking reads wait until the whole buffer is read, EOF or error
+    read_exact: bool,
+    /// blocking reads give up with `Again` after waiting this long
+    read_timeout: Option<Duration>,
+    /// flush the inode after each write, delivering output buffered before
+    sync_writes: bool,
+    /// the device node this is open on
+    rdev: Option<usize>,
+    /// the tty this has exclusive output of, released on close
+    exclusive: Option<Arc<dyn INode>>,
+    /// the threads whose last poll found the inode readable
+    polled_readable: PolledReaders,
 }
 
 impl OpenFileDescription {
-    fn create(options: OpenOptions) -> Arc<RwLock<Self>> {
+    fn create(options: OpenOptions, inode: &Arc<dyn INode>) -> Arc<RwLock<Self>> {
+        let rdev = match inode.metadata() {
+            Ok(Metadata {
+                type_: FileType::CharDevice,
+                rdev,
+                ..
+            }) => Some(rdev),
+            _ => None,
+        };
+        if let Some(rdev) = rdev {
+            device_opened(rdev);
+        }
         Arc::new(RwLock::new(OpenFileDescription {
             offset: 0,
             options,
             flock: Flock::None,
+            read_lowat: 1,
+            read_exact: false,
+            read_timeout: None,
+            sync_writes: false,
+            rdev,
+            exclusive: None,
+            polled_readable: PolledReaders::default(),
         }))
     }
 }
 
+impl Drop for OpenFileDescription {
+    fn drop(&mut self) {
+        // the last file descriptor sharing this description is closed
+        if let Some(rdev) = self.rdev {
+            device_closed(rdev);
+        }
+        if let Some(inode) = self.exclusive.take() {
+            if let Some(tty) = inode.as_any_ref().downcast_ref::<TtyINode>() {
+                tty.release_exclusive(self as *const Self as usize);
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct FileHandle {
     inode: Arc<dyn INode>,
@@ -71,9 +198,10 @@ impl FileHandle {
         pipe: bool,
         fd_cloexec: bool,
     ) -> Self {
+        let description = OpenFileDescription::create(options, &inode);
         return FileHandle {
             inode,
-            description: OpenFileDescription::create(options),
+            description,
             path,
             pipe,
             fd_cloexec,
@@ -91,6 +219,64 @@ impl FileHandle {
         }
     }
 
+    /// Take exclusive output of the tty this is open on until closed, see
+    /// `TtyINode::claim_exclusive`. `NotSupported` for other inodes.
+    pub fn claim_exclusive(&self) -> Result<()> {
+        let tty = self
+            .inode
+            .as_any_ref()
+            .downcast_ref::<TtyINode>()
+            .ok_or(NotSupported)?;
+        let mut description = self.description.write();
+        if description.exclusive.is_some() {
+            return Ok(());
+        }
+        tty.claim_exclusive(&*description as *const OpenFileDescription as usize)?;
+        description.exclusive = Some(self.inode.clone());
+        Ok(())
+    }
+
+    /// Copy console output to `file`, see `TtyINode::set_log_file`.
+    /// `NotSupported` for inodes other than a tty.
+    pub fn set_log_file(&self, file: Option<Arc<dyn INode>>) -> Result<()> {
+        let tty = self
+            .inode
+            .as_any_ref()
+            .downcast_ref::<TtyINode>()
+            .ok_or(NotSupported)?;
+        tty.set_log_file(file)
+    }
+
+    /// Time until the tty this is open on takes writes again, while its
+    /// output rate limit holds output back. `None` for other inodes, and
+    /// for non-blocking files, which get `Again`.
+    pub fn output_wait(&self) -> Option<Duration> {
+        if self.description.read().options.nonblock {
+            return None;
+        }
+        self.inode
+            .as_any_ref()
+            .downcast_ref::<TtyINode>()?
+            .output_wait()
+    }
+
+    /// Wait until a write which failed with `Again` can go on, the pipe
+    /// this is open on having room again. Fails with `Again` for other
+    /// inodes and for non-blocking files, and with `Interrupted` when the
+    /// current thread has a signal to handle.
+    pub async fn wait_writable(&self, eventbus: &Arc<SpinNoIrqLock<EventBus>>) -> Result<()> {
+        if self.description.read().options.nonblock
+            || self.inode.as_any_ref().downcast_ref::<Pipe>().is_none()
+        {
+            return Err(FsError::Again);
+        }
+        match self.wait_ready(None, eventbus).await {
+            WaitOutcome::Ready(ret) => ret.map(|_| ()),
+            WaitOutcome::TimedOut => Err(FsError::Again),
+            WaitOutcome::Interrupted | WaitOutcome::Cancelled => Err(Interrupted),
+        }
+    }
+
     pub fn set_options(&self, arg: usize) {
         let options = &mut self.description.write().options;
         options.nonblock = (arg & O_NONBLOCK) != 0;
@@ -98,32 +284,94 @@ impl FileHandle {
         // options.append = (arg & O_APPEND) != 0;
     }
 
+    /// Flush the inode after each write through this file and its dups, so
+    /// the written bytes show at once, after anything buffered before them.
+    pub fn set_sync_writes(&self, sync: bool) {
+        self.description.write().sync_writes = sync;
+    }
+
     // pub fn get_options(&self) -> usize {
     // let options = self.description.read().options;
     // let mut ret = 0 as usize;
     // }
 
-    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    /// Stop a read by the process group `pgid` if the inode is a tty it is
+    /// not in the foreground of.
+    pub fn check_foreground(&self, pgid: Pgid) -> Result<()> {
+        match self.inode.as_any_ref().downcast_ref::<TtyINode>() {
+            Some(tty) => tty.check_foreground(pgid),
+            None => Ok(()),
+        }
+    }
+
+    /// Read at the file offset, see `read_at`.
+    pub async fn read(
+        &mut self,
+        buf: &mut [u8],
+        eventbus: &Arc<SpinNoIrqLock<EventBus>>,
+    ) -> Result<usize> {
         let offset = self.description.read().offset as usize;
-        let len = self.read_at(offset, buf).await?;
+        let len = self.read_at(offset, buf, eventbus).await?;
         self.description.write().offset += len as u64;
         Ok(len)
     }
 
-    pub async fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+    /// Read at `offset`. A blocking read also wakes on the events of
+    /// `eventbus`, the event bus of the reading process, to end on signals.
+    pub async fn read_at(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        eventbus: &Arc<SpinNoIrqLock<EventBus>>,
+    ) -> Result<usize> {
         // let options = &self.description.read().options;
         if !self.description.read().options.read {
             return Err(FsError::InvalidParam); // TODO: => EBADF
         }
         if !self.description.read().options.nonblock {
             // block
+            let lowat = if self.description.read().read_exact {
+                buf.len()
+            } else {
+                self.description.read().read_lowat.min(buf.len())
+            };
+            let deadline = self
+                .description
+                .read()
+                .read_timeout
+                .map(|t| timer_now() + t);
+            // only a poll of this thread counts, another one may share the
+            // description and not expect anything
+            let polled_readable = match current_thread() {
+                Some(thread) => self.description.write().polled_readable.take(thread.tid),
+                None => false,
+            };
+            let mut total = 0;
             loop {
-                match self.inode.read_at(offset, buf) {
+                match self.inode.read_at(offset + total, &mut buf[total..]) {
+                    Ok(0) => {
+                        // end of file
+                        return Ok(total);
+                    }
                     Ok(read_len) => {
-                        return Ok(read_len);
+                        total += read_len;
+                        if total >= lowat {
+                            return Ok(total);
+                        }
+                    }
+                    // another reader took what a poll just reported, the
+                    // caller expects not to block
+                    Err(FsError::Again) if total == 0 && polled_readable => {
+                        return Err(FsError::Again);
                     }
                     Err(FsError::Again) => {
-                        self.async_poll().await?;
+                        let outcome = self.wait_ready(deadline, eventbus).await;
+                        if let Some(ret) = read_wait_result(total, outcome) {
+                            return ret;
+                        }
+                    }
+                    Err(_) if total > 0 => {
+                        return Ok(total);
                     }
                     Err(err) => {
                         return Err(err);
@@ -131,6 +379,7 @@ impl FileHandle {
                 }
             }
         } else {
+            // the read watermark only applies to blocking reads
             let len = self.inode.read_at(offset, buf)?;
             Ok(len)
         }
@@ -154,6 +403,9 @@ impl FileHandle {
         }
         let len = self.inode.write_at(offset, buf)?;
         TimeSpec::update(&self.inode);
+        if self.description.read().sync_writes {
+            self.inode.sync_data()?;
+        }
         Ok(len)
     }
 
@@ -214,15 +466,209 @@ impl FileHandle {
     }
 
     pub fn poll(&self) -> Result<PollStatus> {
-        self.inode.poll()
+        let status = self.inode.poll()?;
+        self.polled(&status);
+        Ok(status)
     }
 
     pub async fn async_poll(&self) -> Result<PollStatus> {
-        self.inode.async_poll().await
+        let status = self.inode.async_poll().await?;
+        self.polled(&status);
+        Ok(status)
+    }
+
+    /// Note a poll found the inode readable: a blocking read of the same
+    /// thread right after which finds no data fails with `Again` instead of
+    /// waiting.
+    fn polled(&self, status: &PollStatus) {
+        if !status.read {
+            return;
+        }
+        if let Some(thread) = current_thread() {
+            self.description.write().polled_readable.polled(thread.tid);
+        }
+    }
+
+    /// Wait until the inode is readable or has an error, as a blocking read
+    /// does, return which as a `WAIT_EVENT_*` code. Fails with `Again` after
+    /// the read timeout.
+    pub async fn wait_event(&self, eventbus: &Arc<SpinNoIrqLock<EventBus>>) -> Result<usize> {
+        let deadline = self
+            .description
+            .read()
+            .read_timeout
+            .map(|t| timer_now() + t);
+        loop {
+            let status = self.inode.poll()?;
+            if status.error {
+                return Ok(WAIT_EVENT_ERROR);
+            }
+            if status.read {
+                return Ok(WAIT_EVENT_READABLE);
+            }
+            match self.wait_ready(deadline, eventbus).await {
+                WaitOutcome::Ready(ret) => {
+                    ret?;
+                }
+                WaitOutcome::TimedOut => return Err(FsError::Again),
+                WaitOutcome::Interrupted | WaitOutcome::Cancelled => return Err(Interrupted),
+            }
+        }
+    }
+
+    /// Wait for the inode to become ready, until `deadline` if given or the
+    /// current thread has a signal to handle. Every blocking read, and a
+    /// blocking write to a full pipe, waits here, so all devices end their
+    /// waits the same way. `eventbus` is the event bus of the process,
+    /// which a signal sets an event on, the syscall passes it in so the
+    /// process is not locked here.
+    async fn wait_ready(
+        &self,
+        deadline: Option<Duration>,
+        eventbus: &Arc<SpinNoIrqLock<EventBus>>,
+    ) -> WaitOutcome {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct WaitReadyFuture<'a> {
+            poll: Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>>,
+            deadline: Option<Duration>,
+            /// the timer to wake at `deadline` is added
+            timer_added: bool,
+            thread: Option<Arc<Thread>>,
+            eventbus: &'a Arc<SpinNoIrqLock<EventBus>>,
+            /// id of the callback subscribed to `eventbus`, while it was not
+            /// called: it drops itself once called
+            subscription: Option<usize>,
+            /// the callback was called
+            woken: Arc<AtomicBool>,
+        }
+
+        impl<'a> Drop for WaitReadyFuture<'a> {
+            fn drop(&mut self) {
+                // a wait which ended another way leaves no callback behind
+                if let Some(id) = self.subscription.take() {
+                    if !self.woken.load(Ordering::Relaxed) {
+                        self.eventbus.lock().unsubscribe(id);
+                    }
+                }
+            }
+        }
+
+        impl<'a> Future for WaitReadyFuture<'a> {
+            type Output = WaitOutcome;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                let ready = self.poll.as_mut().poll(cx);
+                let timed_out = self
+                    .deadline
+                    .map_or(false, |deadline| timer_now() >= deadline);
+                let signaled = self
+                    .thread
+                    .as_ref()
+                    .map_or(false, |thread| thread.has_signal_to_handle());
+                if let Some(outcome) = wait_outcome(ready, timed_out, signaled) {
+                    return Poll::Ready(outcome);
+                }
+                if let Some(deadline) = self.deadline {
+                    if !self.timer_added {
+                        self.timer_added = true;
+                        let waker = cx.waker().clone();
+                        NAIVE_TIMER
+                            .lock()
+                            .add(deadline, Box::new(move |_| waker.wake()));
+                    }
+                }
+                // subscribe again only once the last callback was called
+                if self.subscription.is_none() || self.woken.load(Ordering::Relaxed) {
+                    let mut eventbus = self.eventbus.lock();
+                    let waker = cx.waker().clone();
+                    let woken = Arc::new(AtomicBool::new(false));
+                    let called = woken.clone();
+                    let id = eventbus.subscribe(Box::new({
+                        move |_| {
+                            called.store(true, Ordering::Relaxed);
+                            waker.wake_by_ref();
+                            true
+                        }
+                    }));
+                    drop(eventbus);
+                    self.subscription = Some(id);
+                    self.woken = woken;
+                }
+                Poll::Pending
+            }
+        }
+
+        WaitReadyFuture {
+            poll: self.inode.async_poll(),
+            deadline,
+            timer_added: false,
+            thread: current_thread(),
+            eventbus,
+            subscription: None,
+            woken: Arc::new(AtomicBool::new(false)),
+        }
+        .await
     }
 
     pub fn io_control(&self, cmd: u32, arg: usize) -> Result<usize> {
-        self.inode.io_control(cmd, arg)
+        match cmd as usize {
+            FIOGETLOWAT => {
+                let lowat = self.description.read().read_lowat;
+                unsafe { *(arg as *mut i32) = lowat as i32 };
+                Ok(0)
+            }
+            FIOSETLOWAT => {
+                let lowat = unsafe { *(arg as *const i32) };
+                if lowat <= 0 {
+                    return Err(FsError::InvalidParam);
+                }
+                self.description.write().read_lowat = lowat as usize;
+                Ok(0)
+            }
+            FIOGETEXACT => {
+                let exact = self.description.read().read_exact;
+                unsafe { *(arg as *mut i32) = exact as i32 };
+                Ok(0)
+            }
+            FIOSETEXACT => {
+                let exact = unsafe { *(arg as *const i32) } != 0;
+                self.description.write().read_exact = exact;
+                Ok(0)
+            }
+            FIOGETRCVTIMEO => {
+                let timeout = self.description.read().read_timeout.unwrap_or_default();
+                let timespec = TimeSpec {
+                    sec: timeout.as_secs() as usize,
+                    nsec: timeout.subsec_nanos() as usize,
+                };
+                unsafe { *(arg as *mut TimeSpec) = timespec };
+                Ok(0)
+            }
+            FIOSETRCVTIMEO => {
+                let timespec = unsafe { *(arg as *const TimeSpec) };
+                if timespec.nsec >= 1_000_000_000 {
+                    return Err(FsError::InvalidParam);
+                }
+                // zero means no timeout, as SO_RCVTIMEO
+                let timeout = Some(timespec.to_duration()).filter(|t| *t != Duration::default());
+                self.description.write().read_timeout = timeout;
+                Ok(0)
+            }
+            // devices with a bounded output buffer answer themselves
+            FIOGETOSPACE => match self.inode.io_control(cmd, arg) {
+                Err(FsError::NotSupported) => {
+                    let space = if self.inode.poll()?.write {
+                        i32::MAX
+                    } else {
+                        0
+                    };
+                    unsafe { *(arg as *mut i32) = space };
+                    Ok(0)
+                }
+                result => result,
+            },
+            _ => self.inode.io_control(cmd, arg),
+        }
     }
 
     pub fn mmap(&mut self, area: MMapArea) -> Result<()> {
@@ -267,3 +713,110 @@ impl fmt::Debug for FileHandle {
             .finish();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{read_wait_result, wait_outcome, PolledReaders, WaitOutcome};
+    use core::task::Poll;
+    use rcore_fs::vfs::{FsError, PollStatus};
+
+    #[test]
+    fn drained_after_poll_fails_only_the_poller() {
+        // thread 1 polls readable, thread 2 sharing the description takes
+        // the data before thread 1 reads
+        let mut readers = PolledReaders::default();
+        readers.polled(1);
+        // thread 2 did not poll, its blocking read waits
+        assert!(!readers.take(2));
+        // thread 1 was told it is readable, its read fails with `Again`
+        assert!(readers.take(1));
+        // and its next blocking read waits again
+        assert!(!readers.take(1));
+    }
+
+    #[test]
+    fn polls_of_several_threads() {
+        let mut readers = PolledReaders::default();
+        readers.polled(1);
+        readers.polled(2);
+        // a read of thread 1 leaves the poll of thread 2
+        assert!(readers.take(1));
+        assert!(readers.take(2));
+    }
+
+    fn readable() -> Poll<rcore_fs::vfs::Result<PollStatus>> {
+        Poll::Ready(Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        }))
+    }
+
+    #[test]
+    fn each_wait_outcome() {
+        assert!(matches!(
+            wait_outcome(readable(), false, false),
+            Some(WaitOutcome::Ready(Ok(_)))
+        ));
+        assert!(matches!(
+            wait_outcome(Poll::Ready(Err(FsError::DeviceError)), false, false),
+            Some(WaitOutcome::Ready(Err(FsError::DeviceError)))
+        ));
+        assert!(matches!(
+            wait_outcome(Poll::Ready(Err(FsError::Interrupted)), false, false),
+            Some(WaitOutcome::Cancelled)
+        ));
+        assert!(matches!(
+            wait_outcome(Poll::Pending, true, false),
+            Some(WaitOutcome::TimedOut)
+        ));
+        assert!(matches!(
+            wait_outcome(Poll::Pending, false, true),
+            Some(WaitOutcome::Interrupted)
+        ));
+        assert!(wait_outcome(Poll::Pending, false, false).is_none());
+    }
+
+    #[test]
+    fn data_wins_over_timeout_and_signal() {
+        assert!(matches!(
+            wait_outcome(readable(), true, true),
+            Some(WaitOutcome::Ready(Ok(_)))
+        ));
+        assert!(matches!(
+            wait_outcome(Poll::Ready(Err(FsError::Interrupted)), true, true),
+            Some(WaitOutcome::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn timeout_wins_over_signal() {
+        assert!(matches!(
+            wait_outcome(Poll::Pending, true, true),
+            Some(WaitOutcome::TimedOut)
+        ));
+    }
+
+    #[test]
+    fn signal_after_a_partial_read_keeps_the_count() {
+        assert!(matches!(
+            read_wait_result(5, WaitOutcome::Interrupted),
+            Some(Ok(5))
+        ));
+        assert!(matches!(
+            read_wait_result(5, WaitOutcome::TimedOut),
+            Some(Ok(5))
+        ));
+        assert!(matches!(
+            read_wait_result(0, WaitOutcome::Interrupted),
+            Some(Err(FsError::Interrupted))
+        ));
+        assert!(matches!(
+            read_wait_result(0, WaitOutcome::TimedOut),
+            Some(Err(FsError::Again))
+        ));
+        if let Poll::Ready(ready) = readable() {
+            assert!(read_wait_result(5, WaitOutcome::Ready(ready)).is_none());
+        }
+    }
+}
@@ -4,8 +4,12 @@ use super::ioctl::*;
 use super::FileHandle;
 use crate::fs::epoll::EpollInstance;
 use crate::net::Socket;
+use crate::process::Pgid;
+use crate::sync::{EventBus, SpinNoIrqLock};
 use crate::syscall::{SysError, SysResult};
 use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::time::Duration;
 use rcore_fs::vfs::{MMapArea, PollStatus};
 
 // TODO: merge FileLike to FileHandle ?
@@ -26,9 +30,25 @@ impl FileLike {
         }
     }
 
-    pub async fn read(&mut self, buf: &mut [u8]) -> SysResult {
+    /// Stop a read by the process group `pgid` from a tty it is not in the
+    /// foreground of. Checked before reading, as the process is not to be
+    /// locked while the read blocks.
+    pub fn check_foreground(&self, pgid: Pgid) -> Result<(), SysError> {
+        if let FileLike::File(file) = self {
+            file.check_foreground(pgid)?;
+        }
+        Ok(())
+    }
+
+    /// Read into `buf`, `eventbus` is the event bus of the reading process,
+    /// see `FileHandle::read_at`.
+    pub async fn read(
+        &mut self,
+        buf: &mut [u8],
+        eventbus: &Arc<SpinNoIrqLock<EventBus>>,
+    ) -> SysResult {
         let len = match self {
-            FileLike::File(file) => file.read(buf).await?,
+            FileLike::File(file) => file.read(buf, eventbus).await?,
             FileLike::Socket(socket) => socket.read(buf).0?,
             FileLike::EpollInstance(_) => {
                 return Err(SysError::ENOSYS);
@@ -46,6 +66,25 @@ impl FileLike {
         };
         Ok(len)
     }
+    /// see `FileHandle::output_wait`
+    pub fn output_wait(&self) -> Option<Duration> {
+        match self {
+            FileLike::File(file) => file.output_wait(),
+            _ => None,
+        }
+    }
+
+    /// see `FileHandle::wait_writable`
+    pub async fn wait_writable(
+        &self,
+        eventbus: &Arc<SpinNoIrqLock<EventBus>>,
+    ) -> Result<(), SysError> {
+        match self {
+            FileLike::File(file) => file.wait_writable(eventbus).await.map_err(Into::into),
+            _ => Err(SysError::EAGAIN),
+        }
+    }
+
     pub fn ioctl(&mut self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> SysResult {
         match self {
             FileLike::File(file) => file.io_control(request as u32, arg1).map_err(Into::into),
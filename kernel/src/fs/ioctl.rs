@@ -3,6 +3,8 @@
 // higher 2 bits: 01 = write, 10 = read
 #![allow(dead_code)]
 
+use super::devfs::{FbFixScreeninfo, FbVarScreeninfo};
+use crate::syscall::TimeSpec;
 use bitflags::*;
 
 #[cfg(not(target_arch = "mips"))]
@@ -15,6 +17,16 @@ pub const TCSETS: usize = 0x5402;
 #[cfg(target_arch = "mips")]
 pub const TCSETS: usize = 0x540E;
 
+#[cfg(not(target_arch = "mips"))]
+pub const TCSETSW: usize = 0x5403;
+#[cfg(target_arch = "mips")]
+pub const TCSETSW: usize = 0x540F;
+
+#[cfg(not(target_arch = "mips"))]
+pub const TCSETSF: usize = 0x5404;
+#[cfg(target_arch = "mips")]
+pub const TCSETSF: usize = 0x5410;
+
 #[cfg(not(target_arch = "mips"))]
 pub const TIOCGPGRP: usize = 0x540F;
 // _IOR('t', 119, int)
@@ -33,6 +45,12 @@ pub const TIOCGWINSZ: usize = 0x5413;
 #[cfg(target_arch = "mips")]
 pub const TIOCGWINSZ: usize = 0x4_008_74_68;
 
+#[cfg(not(target_arch = "mips"))]
+pub const TIOCSWINSZ: usize = 0x5414;
+// _IOW('t', 103, struct winsize)
+#[cfg(target_arch = "mips")]
+pub const TIOCSWINSZ: usize = 0x8_008_74_67;
+
 #[cfg(not(target_arch = "mips"))]
 pub const FIONCLEX: usize = 0x5450;
 #[cfg(target_arch = "mips")]
@@ -43,6 +61,11 @@ pub const FIOCLEX: usize = 0x5451;
 #[cfg(target_arch = "mips")]
 pub const FIOCLEX: usize = 0x6601;
 
+#[cfg(not(target_arch = "mips"))]
+pub const FIONREAD: usize = 0x541B;
+#[cfg(target_arch = "mips")]
+pub const FIONREAD: usize = 0x467F;
+
 // rustc using pipe and ioctl pipe file with this request id
 // for non-blocking/blocking IO control setting
 #[cfg(not(target_arch = "mips"))]
@@ -50,7 +73,390 @@ pub const FIONBIO: usize = 0x5421;
 #[cfg(target_arch = "mips")]
 pub const FIONBIO: usize = 0x667E;
 
+// Watchdog requests
+// _IOR('W', 4, int)
+#[cfg(not(target_arch = "mips"))]
+pub const WDIOC_SETOPTIONS: usize = 0x8004_5704;
+#[cfg(target_arch = "mips")]
+pub const WDIOC_SETOPTIONS: usize = 0x4004_5704;
+// _IOR('W', 5, int)
+#[cfg(not(target_arch = "mips"))]
+pub const WDIOC_KEEPALIVE: usize = 0x8004_5705;
+#[cfg(target_arch = "mips")]
+pub const WDIOC_KEEPALIVE: usize = 0x4004_5705;
+// _IOWR('W', 6, int)
+pub const WDIOC_SETTIMEOUT: usize = 0xC004_5706;
+// _IOR('W', 7, int)
+#[cfg(not(target_arch = "mips"))]
+pub const WDIOC_GETTIMEOUT: usize = 0x8004_5707;
+#[cfg(target_arch = "mips")]
+pub const WDIOC_GETTIMEOUT: usize = 0x4004_5707;
+/// WDIOC_SETOPTIONS: turn off the watchdog
+pub const WDIOS_DISABLECARD: i32 = 0x0001;
+/// WDIOC_SETOPTIONS: turn on the watchdog
+pub const WDIOS_ENABLECARD: i32 = 0x0004;
+
+// Linux framebuffer requests
+pub const FBIOGET_VSCREENINFO: usize = 0x4600;
+pub const FBIOPUT_VSCREENINFO: usize = 0x4601;
+pub const FBIOGET_FSCREENINFO: usize = 0x4602;
+
+// Linux vcio request, _IOWR(100, 0, char *)
+/// pass a property request to the firmware, the buffer is at least the
+/// size word, the response code and the end tag, its size word says how
+/// long it is
+pub const MBOX_PROPERTY: usize = 0xc008_6400;
+
+// rCore specific tty requests, after the Linux ones in the 'T' range.
+// The argument is a pointer to int unless noted otherwise.
+
+/// get the size up to which writes are not interleaved
+pub const TIOCGATOMIC: usize = 0x5480;
+/// set the size up to which writes are not interleaved
+pub const TIOCSATOMIC: usize = 0x5481;
+/// get the output buffer size, pending output is printed once it is reached
+pub const TIOCGFLUSHSZ: usize = 0x5482;
+/// set the output buffer size, 0 to print every write at once
+pub const TIOCSFLUSHSZ: usize = 0x5483;
+/// get whether pending output is printed at every newline
+pub const TIOCGAUTOFLUSH: usize = 0x5484;
+/// set whether pending output is printed at every newline
+pub const TIOCSAUTOFLUSH: usize = 0x5485;
+/// get whether color (SGR) escape sequences are removed from output
+pub const TIOCGSTRIPSGR: usize = 0x5486;
+/// set whether color (SGR) escape sequences are removed from output
+pub const TIOCSSTRIPSGR: usize = 0x5487;
+/// get the next input byte without consuming it, -1 if there is none
+pub const TIOCPEEK: usize = 0x5488;
+/// get the index of the serial port the console is on
+pub const TIOCGCONSPORT: usize = 0x5489;
+/// move the console to another serial port
+pub const TIOCSCONSPORT: usize = 0x548A;
+/// get the tty statistics, as `struct TtyStats`
+pub const TIOCGSTATS: usize = 0x548B;
+/// reset the tty statistics, no argument
+pub const TIOCZSTATS: usize = 0x548C;
+/// copy the unread input out, as `struct TtyInput`, debug builds only
+pub const TIOCGINPUT: usize = 0x548D;
+/// replace the unread input, as `struct TtyInput`, debug builds only
+pub const TIOCSINPUT: usize = 0x548E;
+/// get whether written lines are prefixed with a timestamp
+pub const TIOCGLINESTAMP: usize = 0x54A0;
+/// set whether written lines are prefixed with a timestamp, default off
+pub const TIOCSLINESTAMP: usize = 0x54A1;
+/// get whether output lines wrap at the window width
+pub const TIOCGWRAP: usize = 0x54A2;
+/// set whether output lines wrap at the window width, default off
+pub const TIOCSWRAP: usize = 0x54A3;
+/// copy console output to the end of the regular file open at the given fd,
+/// -1 to stop
+pub const TIOCSLOGFD: usize = 0x54A4;
+/// get what happens to input arriving while the input buffer is full
+pub const TIOCGOVERFLOW: usize = 0x54A5;
+/// set what happens to input arriving while the input buffer is full,
+/// one of the `OVERFLOW_*` values, default `OVERFLOW_BELL`
+pub const TIOCSOVERFLOW: usize = 0x54A6;
+/// take the next key from the input as `struct KeyEvent`, decoding escape
+/// sequences of special keys. EAGAIN if there is none yet
+pub const TIOCGKEY: usize = 0x54A7;
+/// run the self tests of the drivers, get the `SELFTEST_*` bits of those
+/// that passed, debug builds only
+pub const TIOCSELFTEST: usize = 0x54A8;
+/// get the length limit of the line being edited in canonical mode
+pub const TIOCGMAXCANON: usize = 0x54A9;
+/// set the length limit of the line being edited in canonical mode,
+/// default 4096
+pub const TIOCSMAXCANON: usize = 0x54AA;
+/// get what happens to input reaching the canonical line length limit
+pub const TIOCGCANONFULL: usize = 0x54AB;
+/// set what happens to input reaching the canonical line length limit,
+/// one of the `CANON_FULL_*` values, default `CANON_FULL_BELL`
+pub const TIOCSCANONFULL: usize = 0x54AC;
+/// get the capacity of the input buffer, counting the line being edited
+pub const TIOCGINPUTMAX: usize = 0x54AD;
+/// set the capacity of the input buffer, at most 65536, default 4096.
+/// EBUSY if the buffer holds more than that
+pub const TIOCSINPUTMAX: usize = 0x54AE;
+/// get whether input arriving while no one reads or polls the tty is kept
+pub const TIOCGTYPEAHEAD: usize = 0x54C0;
+/// set whether input arriving while no one reads or polls the tty is kept,
+/// default on. Signal chars still work while it is off
+pub const TIOCSTYPEAHEAD: usize = 0x54C1;
+/// get the sequence number of the next input byte read, as u64. Every byte
+/// made readable or dropped takes one, so a gap means lost input
+pub const TIOCGINSEQ: usize = 0x54C2;
+/// get whether resize and signal events are queued for TIOCGEVENT
+pub const TIOCGEVMODE: usize = 0x54C3;
+/// set whether resize and signal events are queued for TIOCGEVENT, default
+/// off. While on, poll reports the tty readable when an event is queued
+pub const TIOCSEVMODE: usize = 0x54C4;
+/// take the next event as `struct TtyEvent`, resize and signal events
+/// before keys. EAGAIN if there is none yet
+pub const TIOCGEVENT: usize = 0x54C5;
+/// get the delay between echoed chars in microseconds
+pub const TIOCGECHODELAY: usize = 0x54C6;
+/// set the delay between echoed chars in microseconds, at most 100000,
+/// default 0. Input is still readable at once, the echo is queued and
+/// printed as the delay allows
+pub const TIOCSECHODELAY: usize = 0x54C7;
+/// get whether written output is shown as a hex dump
+pub const TIOCGHEXDUMP: usize = 0x54C8;
+/// set whether written output is shown as a hex dump like `hexdump -C`,
+/// each write on rows of its own, default off. Turning it on restarts the
+/// offsets at 0
+pub const TIOCSHEXDUMP: usize = 0x54C9;
+/// wait until written output has left the serial port, not only the tty
+/// buffer, for programs timing hardware events after their output
+pub const TIOCDRAIN: usize = 0x54CA;
+/// record received bytes into a ring of the given size in bytes, at most
+/// 65536, dropping what was recorded before. 0 stops recording
+pub const TIOCSRECORD: usize = 0x54CB;
+/// copy the recorded bytes out as `struct TtyInput`, oldest first. Only the
+/// newest ones if the buffer is too small
+pub const TIOCGRECORD: usize = 0x54CC;
+/// set the baud rate of the console port to the closest one it can do,
+/// after draining its output. Returns the rate set
+pub const TIOCSBAUD: usize = 0x54CD;
+/// get the limit of the rate written output is printed at, in bytes per
+/// second, 0 if unlimited
+pub const TIOCGOUTRATE: usize = 0x54CE;
+/// set the limit of the rate written output is printed at, in bytes per
+/// second, default 0 for unlimited. Writers block to stay under it
+pub const TIOCSOUTRATE: usize = 0x54CF;
+/// start capturing the output printed to the console, echo included, to
+/// memory, dropping what was captured before. 0 stops capturing
+pub const TIOCSCAPTURE: usize = 0x54D0;
+/// take the captured output as `struct TtyInput`, oldest first, what does
+/// not fit stays for the next call. Returns the number of bytes dropped
+/// since the last call as more than 65536 were captured
+pub const TIOCGCAPTURE: usize = 0x54D1;
+/// get whether `\r`, `\n` and `\r\n` input are all taken as one `\n`
+pub const TIOCGNLFOLD: usize = 0x54D2;
+/// set whether `\r`, `\n` and `\r\n` input are all taken as one `\n`,
+/// default off. ICRNL, INLCR and IGNCR do not apply while on
+pub const TIOCSNLFOLD: usize = 0x54D3;
+/// get what replaces input which is not valid UTF-8
+pub const TIOCGUTF8ERR: usize = 0x54D4;
+/// set what replaces input which is not valid UTF-8, one of the
+/// `UTF8_ERR_*` values, default `UTF8_ERR_REPLACE`. Input is only checked
+/// in canonical mode with IUTF8 set, other input is taken as raw bytes
+pub const TIOCSUTF8ERR: usize = 0x54D5;
+/// get whether a read returns at most one line
+pub const TIOCGLINEREAD: usize = 0x54D6;
+/// set whether a read returns at most one line, up to and including its
+/// `\n`, also outside canonical mode. Default off
+pub const TIOCSLINEREAD: usize = 0x54D7;
+/// drop the unfinished UTF-8 sequence of the input check, as after a
+/// corrupted stream. Done by itself when the input stops or starts being
+/// checked
+pub const TIOCUTF8RESET: usize = 0x54D8;
+/// print the bytes given as `struct TtyInput` at once, ahead of pending
+/// output, past the output rate limit and exclusive output. For messages
+/// which must not be held up, as kernel messages never are
+pub const TIOCURGENT: usize = 0x54D9;
+/// get whether the line editor is on
+pub const TIOCGLINEEDIT: usize = 0x54DA;
+/// set whether the line editor is on, default off. In canonical mode the
+/// left and right arrow, Home, End and Delete keys then move within the
+/// line and edit it at the cursor. Turning it off moves to the line end
+pub const TIOCSLINEEDIT: usize = 0x54DB;
+/// get the number of input bytes dropped as the input buffer was full
+/// since the last TIOCZDROPS, as `u64`. Not reset by TIOCZSTATS
+pub const TIOCGDROPS: usize = 0x54DC;
+/// reset the count of dropped input bytes, no argument
+pub const TIOCZDROPS: usize = 0x54DD;
+/// start keeping what the console shows in a screen buffer of the window
+/// size, blank at first. 0 stops it. Output printed by the kernel itself
+/// does not go through the tty and is not seen
+pub const TIOCSSCREEN: usize = 0x54DE;
+/// copy the screen buffer out as `struct TtyScreen`
+pub const TIOCGSCREEN: usize = 0x54DF;
+/// get the encoding console output is translated to
+pub const TIOCGOUTENC: usize = 0x54E0;
+/// set the encoding console output is translated to from UTF-8, one of the
+/// `OUTPUT_ENCODING_*` values, default `OUTPUT_ENCODING_UTF8`. Chars it has
+/// no byte for are printed as `?`
+pub const TIOCSOUTENC: usize = 0x54E1;
+/// make the reads of the tty blocked right now fail with EINTR, leaving the
+/// input for later reads. For tearing a session down, no argument
+pub const TIOCCANCELREADS: usize = 0x54E2;
+
+// self tests of TIOCSELFTEST
+/// line discipline of a scratch tty, no hardware is touched
+pub const SELFTEST_CONSOLE: i32 = 1;
+
+// input overflow policies of TIOCSOVERFLOW
+/// drop the byte and ring the bell
+pub const OVERFLOW_BELL: usize = 0;
+/// drop the byte silently
+pub const OVERFLOW_DROP: usize = 1;
+/// refuse the byte, its producer keeps it. Serial and keyboard interrupt
+/// handlers can not, the byte is dropped and counted
+pub const OVERFLOW_ERROR: usize = 2;
+
+// policies of TIOCSCANONFULL
+/// drop bytes past the limit and ring the bell, only a newline is taken
+pub const CANON_FULL_BELL: usize = 0;
+/// make the line readable without a newline once it reaches the limit
+pub const CANON_FULL_DELIVER: usize = 1;
+
+// policies of TIOCSUTF8ERR
+/// replace each invalid sequence with U+FFFD
+pub const UTF8_ERR_REPLACE: usize = 0;
+/// drop invalid sequences
+pub const UTF8_ERR_SKIP: usize = 1;
+/// replace a run of invalid sequences with one U+FFFD, up to the next
+/// valid one
+pub const UTF8_ERR_RESYNC: usize = 2;
+
+// output encodings of TIOCSOUTENC
+/// UTF-8 as written, no translation
+pub const OUTPUT_ENCODING_UTF8: usize = 0;
+/// code page 437 of the IBM PC, with its box drawing chars
+pub const OUTPUT_ENCODING_CP437: usize = 1;
+
+// rCore specific requests handled for any file
+/// get the number of bytes a blocking read waits for
+pub const FIOGETLOWAT: usize = 0x5490;
+/// set the number of bytes a blocking read waits for, default 1
+pub const FIOSETLOWAT: usize = 0x5491;
+/// get the timeout of blocking reads as `struct timespec`, zero for none
+pub const FIOGETRCVTIMEO: usize = 0x5492;
+/// set the timeout of blocking reads as `struct timespec`, zero for none
+pub const FIOSETRCVTIMEO: usize = 0x5493;
+/// get whether blocking reads fill the whole buffer
+pub const FIOGETEXACT: usize = 0x5494;
+/// set whether blocking reads fill the whole buffer, returning less only on
+/// end of file, interrupt, timeout or error
+pub const FIOSETEXACT: usize = 0x5495;
+/// get the number of bytes a write can take without blocking, a large value
+/// for files whose writes never block
+pub const FIOGETOSPACE: usize = 0x5496;
+/// wait until the file is readable or has an error, as a blocking read
+/// does, honoring the read timeout. Returns a `WAIT_EVENT_*` code
+pub const FIOWAITEVENT: usize = 0x5497;
+
+// events of FIOWAITEVENT
+/// a read would not block
+pub const WAIT_EVENT_READABLE: usize = 1;
+/// the device reported an error or hangup
+pub const WAIT_EVENT_ERROR: usize = 2;
+
+// rCore specific /dev/mem requests
+/// get the bytes per memory access
+pub const MEMGWIDTH: usize = 0x54B0;
+/// set the bytes per memory access, 1, 2 or 4, default 1
+pub const MEMSWIDTH: usize = 0x54B1;
+
+// rCore specific /dev/checksum requests
+/// get the CRC-32 of the bytes written since the last reset, as u32
+pub const CRCIOCGET: usize = 0x54B2;
+/// start the CRC-32 over, no argument
+pub const CRCIOCRESET: usize = 0x54B3;
+
+// rCore specific /dev/timer requests
+/// sleep the given number of microseconds, as i32, rounded up to a
+/// scheduler tick
+pub const TIMERIOCDELAY: usize = 0x54B4;
+
+/// Memory the argument of a request points to
+pub enum IoctlArg {
+    /// the kernel reads this many bytes from it
+    In(usize),
+    /// the kernel writes this many bytes to it
+    Out(usize),
+    /// the kernel reads this many bytes from it and writes them back
+    InOut(usize),
+}
+
+/// Expected argument of the requests handled by the kernel itself,
+/// `None` for requests without a pointer argument or unknown to it.
+pub fn ioctl_arg(request: usize) -> Option<IoctlArg> {
+    use core::mem::size_of;
+    match request {
+        TCGETS => Some(IoctlArg::Out(size_of::<Termios>())),
+        TCSETS | TCSETSW | TCSETSF => Some(IoctlArg::In(size_of::<Termios>())),
+        TIOCGPGRP | TIOCGATOMIC | TIOCGFLUSHSZ | TIOCGAUTOFLUSH | TIOCGSTRIPSGR | TIOCPEEK
+        | TIOCGCONSPORT | TIOCGLINESTAMP | TIOCGWRAP | TIOCGOVERFLOW | TIOCGINPUTMAX
+        | TIOCGTYPEAHEAD | TIOCGEVMODE | TIOCGECHODELAY | TIOCGHEXDUMP | TIOCGOUTRATE
+        | TIOCGNLFOLD | TIOCGUTF8ERR | TIOCGLINEREAD | TIOCGLINEEDIT | TIOCGOUTENC
+        | TIOCGMAXCANON | TIOCGCANONFULL | FIOGETLOWAT | FIOGETEXACT | FIOGETOSPACE | FIONREAD
+        | MEMGWIDTH | TIOCSELFTEST | CRCIOCGET => Some(IoctlArg::Out(size_of::<i32>())),
+        TIOCSPGRP | TIOCSATOMIC | TIOCSFLUSHSZ | TIOCSAUTOFLUSH | TIOCSSTRIPSGR | FIOSETLOWAT
+        | TIOCSCONSPORT | TIOCSLINESTAMP | TIOCSWRAP | TIOCSLOGFD | TIOCSOVERFLOW
+        | TIOCSINPUTMAX | TIOCSTYPEAHEAD | TIOCSEVMODE | TIOCSECHODELAY | TIOCSHEXDUMP
+        | TIOCSRECORD | TIOCSBAUD | TIOCSOUTRATE | TIOCSCAPTURE | TIOCSNLFOLD | TIOCSUTF8ERR
+        | TIOCSLINEREAD | TIOCSLINEEDIT | TIOCSSCREEN | TIOCSOUTENC | TIOCSMAXCANON
+        | TIOCSCANONFULL | FIOSETEXACT | FIONBIO | MEMSWIDTH | TIMERIOCDELAY => {
+            Some(IoctlArg::In(size_of::<i32>()))
+        }
+        TIOCGWINSZ => Some(IoctlArg::Out(size_of::<Winsize>())),
+        TIOCSWINSZ => Some(IoctlArg::In(size_of::<Winsize>())),
+        TIOCGSTATS => Some(IoctlArg::Out(size_of::<TtyStats>())),
+        TIOCGKEY => Some(IoctlArg::Out(size_of::<KeyEvent>())),
+        TIOCGINSEQ | TIOCGDROPS => Some(IoctlArg::Out(size_of::<u64>())),
+        TIOCGEVENT => Some(IoctlArg::Out(size_of::<TtyEvent>())),
+        TIOCGSCREEN => Some(IoctlArg::Out(size_of::<TtyScreen>())),
+        TIOCGINPUT | TIOCGRECORD | TIOCGCAPTURE => Some(IoctlArg::Out(size_of::<TtyInput>())),
+        TIOCSINPUT | TIOCURGENT => Some(IoctlArg::In(size_of::<TtyInput>())),
+        WDIOC_SETOPTIONS => Some(IoctlArg::In(size_of::<i32>())),
+        WDIOC_SETTIMEOUT => Some(IoctlArg::InOut(size_of::<i32>())),
+        WDIOC_GETTIMEOUT => Some(IoctlArg::Out(size_of::<i32>())),
+        FIOGETRCVTIMEO => Some(IoctlArg::Out(size_of::<TimeSpec>())),
+        FIOSETRCVTIMEO => Some(IoctlArg::In(size_of::<TimeSpec>())),
+        FBIOGET_VSCREENINFO => Some(IoctlArg::Out(size_of::<FbVarScreeninfo>())),
+        FBIOPUT_VSCREENINFO => Some(IoctlArg::InOut(size_of::<FbVarScreeninfo>())),
+        FBIOGET_FSCREENINFO => Some(IoctlArg::Out(size_of::<FbFixScreeninfo>())),
+        // the mailbox checks the rest of the buffer against its size word
+        MBOX_PROPERTY => Some(IoctlArg::InOut(3 * size_of::<u32>())),
+        _ => None,
+    }
+}
+
+/// Buffer the argument of a request points to, its address and its length
+/// in elements are the first two fields of the argument
+pub enum IoctlBuf {
+    /// the kernel reads from it, elements of this many bytes
+    In(usize),
+    /// the kernel writes to it, elements of this many bytes, and writes
+    /// the argument back
+    Out(usize),
+}
+
+/// Buffer of the requests whose argument points to one, `None` for the
+/// others.
+pub fn ioctl_buf(request: usize) -> Option<IoctlBuf> {
+    use core::mem::size_of;
+    match request {
+        TIOCGSCREEN => Some(IoctlBuf::Out(size_of::<ScreenCell>())),
+        TIOCGINPUT | TIOCGRECORD | TIOCGCAPTURE => Some(IoctlBuf::Out(1)),
+        TIOCSINPUT | TIOCURGENT => Some(IoctlBuf::In(1)),
+        _ => None,
+    }
+}
+
 // ref: https://www.man7.org/linux/man-pages/man3/termios.3.html
+// c_iflag constants
+bitflags! {
+    pub struct InputModes : u32 {
+        const IGNBRK = 0o000001;
+        const BRKINT = 0o000002;
+        const IGNPAR = 0o000004;
+        const PARMRK = 0o000010;
+        const INPCK = 0o000020;
+        const ISTRIP = 0o000040;
+        const INLCR = 0o000100;
+        const IGNCR = 0o000200;
+        const ICRNL = 0o000400;
+        const IUCLC = 0o001000;
+        const IXON = 0o002000;
+        const IXANY = 0o004000;
+        const IXOFF = 0o010000;
+        const IMAXBEL = 0o020000;
+        const IUTF8 = 0o040000;
+    }
+}
+
 // c_lflag constants
 bitflags! {
     pub struct LocalModes : u32 {
@@ -73,6 +479,28 @@ bitflags! {
     }
 }
 
+// c_cc indexes
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSWTC: usize = 7;
+pub const VSTART: usize = 8;
+pub const VSTOP: usize = 9;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VREPRINT: usize = 12;
+pub const VDISCARD: usize = 13;
+pub const VWERASE: usize = 14;
+pub const VLNEXT: usize = 15;
+pub const VEOL2: usize = 16;
+
+/// a c_cc value which disables the special character
+pub const POSIX_VDISABLE: u8 = 0;
+
 // Ref: https://www.man7.org/linux/man-pages/man3/termios.3.html
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -111,12 +539,12 @@ impl Default for Termios {
                 17,  // VSTART
                 19,  // VSTOP
                 26,  // VSUSP Ctrl-Z
-                255, // VEOL
+                0,   // VEOL disabled
                 18,  // VREPAINT
                 15,  // VDISCARD
                 23,  // VWERASE
                 22,  // VLNEXT
-                255, // VEOL2
+                0,   // VEOL2 disabled
                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
             ],
             ispeed: 0,
@@ -133,3 +561,140 @@ pub struct Winsize {
     xpixel: u16,
     ypixel: u16,
 }
+
+impl Winsize {
+    /// Width of the window in characters, 0 if unknown
+    pub fn columns(&self) -> usize {
+        self.ws_col as usize
+    }
+
+    /// Height of the window in characters, 0 if unknown
+    pub fn rows(&self) -> usize {
+        self.row as usize
+    }
+}
+
+/// Counters of a tty, for TIOCGSTATS
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TtyStats {
+    /// bytes written by programs and printed to the console
+    pub bytes_written: u64,
+}
+
+/// A key read by TIOCGKEY
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct KeyEvent {
+    /// the input byte, or one of the `KEY_*` codes for special keys
+    pub key: u32,
+    /// `KEYMOD_*` bits
+    pub modifiers: u32,
+}
+
+// special keys of `KeyEvent`, above all byte values
+pub const KEY_UP: u32 = 0x100;
+pub const KEY_DOWN: u32 = 0x101;
+pub const KEY_RIGHT: u32 = 0x102;
+pub const KEY_LEFT: u32 = 0x103;
+pub const KEY_HOME: u32 = 0x104;
+pub const KEY_END: u32 = 0x105;
+pub const KEY_INSERT: u32 = 0x106;
+pub const KEY_DELETE: u32 = 0x107;
+pub const KEY_PAGEUP: u32 = 0x108;
+pub const KEY_PAGEDOWN: u32 = 0x109;
+/// F1 to F12 follow in order
+pub const KEY_F1: u32 = 0x110;
+pub const KEY_F6: u32 = KEY_F1 + 5;
+pub const KEY_F11: u32 = KEY_F1 + 10;
+
+// modifiers of `KeyEvent`
+pub const KEYMOD_SHIFT: u32 = 1;
+pub const KEYMOD_ALT: u32 = 2;
+pub const KEYMOD_CTRL: u32 = 4;
+
+/// An event read by TIOCGEVENT
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TtyEvent {
+    /// one of the `TTY_EVENT_*` kinds
+    pub kind: u32,
+    /// meaning depends on the kind
+    pub data: [u32; 2],
+}
+
+// kinds of `TtyEvent`
+/// a key was typed, data is the `key` and `modifiers` of its `KeyEvent`
+pub const TTY_EVENT_KEY: u32 = 1;
+/// TIOCSWINSZ changed the window size, data is the rows and columns
+pub const TTY_EVENT_RESIZE: u32 = 2;
+/// a typed char sent a signal to the foreground group, data is the signal
+pub const TTY_EVENT_SIGNAL: u32 = 3;
+
+/// A user buffer for the screen buffer, for TIOCGSCREEN
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TtyScreen {
+    /// address of an array of `struct ScreenCell`
+    pub cells: usize,
+    /// size of the array in cells, set to the number of cells copied out,
+    /// row by row from the top
+    pub len: usize,
+    /// set to the size of the screen
+    pub rows: u32,
+    pub columns: u32,
+    /// set to the cursor position, from 0
+    pub row: u32,
+    pub column: u32,
+}
+
+/// A cell of the screen buffer
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenCell {
+    /// the char shown, as a code point. A space if none is
+    pub ch: u32,
+    /// colors, 0 to 255 as in SGR `38;5;n`, or `SCREEN_COLOR_DEFAULT`
+    pub fg: u16,
+    pub bg: u16,
+    /// `SCREEN_*` attribute bits
+    pub attrs: u32,
+}
+
+/// color of `ScreenCell` for the default one of the terminal
+pub const SCREEN_COLOR_DEFAULT: u16 = 0xffff;
+
+// attributes of `ScreenCell`
+pub const SCREEN_BOLD: u32 = 1;
+pub const SCREEN_UNDERLINE: u32 = 2;
+pub const SCREEN_REVERSE: u32 = 4;
+
+/// A user buffer of tty input or output, for TIOCGINPUT, TIOCSINPUT,
+/// TIOCGRECORD and TIOCGCAPTURE
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TtyInput {
+    /// address of the bytes
+    pub buf: usize,
+    /// size of the buffer, set to the number of bytes copied out by TIOCGINPUT,
+    /// TIOCGRECORD and TIOCGCAPTURE
+    pub len: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::size_of;
+
+    #[test]
+    fn termios_has_the_libc_layout() {
+        // TCSETS and TCGETS copy the struct termios of musl whole
+        let termios = Termios::default();
+        let base = &termios as *const Termios as usize;
+        assert_eq!(size_of::<Termios>(), 60);
+        assert_eq!(&termios.line as *const u8 as usize - base, 16);
+        assert_eq!(termios.cc.as_ptr() as usize - base, 17);
+        assert_eq!(&termios.ispeed as *const u32 as usize - base, 52);
+        assert_eq!(&termios.ospeed as *const u32 as usize - base, 56);
+    }
+}
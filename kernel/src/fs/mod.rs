@@ -1,17 +1,17 @@
 use alloc::{sync::Arc, vec::Vec};
 
 use rcore_fs::{dev::block_cache::BlockCache, vfs::*};
-use rcore_fs_devfs::{
-    special::{NullINode, ZeroINode},
-    DevFS,
-};
+use rcore_fs_devfs::special::{NullINode, ZeroINode};
 use rcore_fs_mountfs::MountFS;
 use rcore_fs_ramfs::RamFS;
 use rcore_fs_sfs::{INodeImpl, SimpleFileSystem};
 
-use self::devfs::{Fbdev, RandomINode};
+use self::devfs::{register_device, Fbdev, RandomINode, DEV_FS};
 
-pub use self::devfs::{Serial, ShmINode, TTY};
+pub use self::devfs::{
+    cc_report, devices, devices_report, dump_device_state, find_device, release_echo,
+    release_output, Serial, ShmINode, TTY, TTY_ID,
+};
 pub use self::file::*;
 pub use self::file_like::*;
 pub use self::pipe::Pipe;
@@ -27,6 +27,7 @@ mod file_like;
 pub mod ioctl;
 mod pipe;
 mod pseudo;
+mod queue;
 
 // Hard link user programs
 #[cfg(feature = "link_user")]
@@ -73,28 +74,47 @@ lazy_static! {
         let rootfs = MountFS::new(sfs);
         let root = rootfs.root_inode();
 
-        // create DevFS
-        let devfs = DevFS::new();
-        devfs.add("null", Arc::new(NullINode::default())).expect("failed to mknod /dev/null");
-        devfs.add("zero", Arc::new(ZeroINode::default())).expect("failed to mknod /dev/zero");
-        devfs.add("random", Arc::new(RandomINode::new(false))).expect("failed to mknod /dev/random");
-        devfs.add("urandom", Arc::new(RandomINode::new(true))).expect("failed to mknod /dev/urandom");
-        devfs.add("tty", TTY.clone()).expect("failed to mknod /dev/tty");
-        devfs.add("fb0", Arc::new(Fbdev::default())).expect("failed to mknod /dev/fb0");
-        devfs.add("shm", Arc::new(ShmINode::default())).expect("failed to mkdir shm");
+        // register devices to DevFS
+        let tty_id = register_device("tty", TTY.clone()).expect("failed to mknod /dev/tty");
+        assert_eq!(tty_id, TTY_ID);
+        devfs::register_state_dump("tty", TTY.clone());
+        register_device("null", Arc::new(NullINode::default())).expect("failed to mknod /dev/null");
+        register_device("zero", Arc::new(ZeroINode::default())).expect("failed to mknod /dev/zero");
+        register_device("random", Arc::new(RandomINode::new(false))).expect("failed to mknod /dev/random");
+        register_device("urandom", Arc::new(RandomINode::new(true))).expect("failed to mknod /dev/urandom");
+        register_device("fb0", Arc::new(Fbdev::default())).expect("failed to mknod /dev/fb0");
+        register_device("shm", Arc::new(ShmINode::default())).expect("failed to mkdir shm");
+        register_device("checksum", Arc::new(devfs::ChecksumINode::default())).expect("failed to mknod /dev/checksum");
         for (i, serial) in Serial::wrap_all_serial_devices().into_iter().enumerate(){
-            devfs.add(&format!("ttyS{}", i), Arc::new(serial)).expect("failed to add a serial");
+            register_device(&format!("ttyS{}", i), Arc::new(serial)).expect("failed to add a serial");
         }
 
 
+        #[cfg(feature = "board_raspi3")]
+        register_device("timer", Arc::new(devfs::SystemTimerINode::default())).expect("failed to mknod /dev/timer");
+        #[cfg(feature = "board_raspi3")]
+        register_device("power", Arc::new(devfs::PowerINode::default())).expect("failed to mknod /dev/power");
+        #[cfg(feature = "board_raspi3")]
+        register_device("vcio", Arc::new(devfs::MailboxINode::default())).expect("failed to mknod /dev/vcio");
+        #[cfg(feature = "board_raspi3")]
+        register_device("thermal", Arc::new(devfs::ThermalINode::default())).expect("failed to mknod /dev/thermal");
+        #[cfg(feature = "board_raspi3")]
+        {
+            let watchdog = Arc::new(devfs::WatchdogINode::default());
+            register_device("watchdog", watchdog.clone()).expect("failed to mknod /dev/watchdog");
+            devfs::register_hooks(watchdog.metadata().unwrap().rdev, watchdog);
+        }
+        #[cfg(feature = "dev_mem")]
+        register_device("mem", Arc::new(devfs::MemINode::default())).expect("failed to mknod /dev/mem");
+
         #[cfg(feature = "hypervisor")]
-        devfs.add("rvm", Arc::new(crate::rvm::RvmINode::new())).expect("failed to mknod /dev/rvm");
+        register_device("rvm", Arc::new(crate::rvm::RvmINode::new())).expect("failed to mknod /dev/rvm");
 
         // mount DevFS at /dev
         let dev = root.find(true, "dev").unwrap_or_else(|_| {
             root.create("dev", FileType::Dir, 0o666).expect("failed to mkdir /dev")
         });
-        let devfs = dev.mount(devfs).expect("failed to mount DevFS");
+        let devfs = dev.mount(DEV_FS.clone()).expect("failed to mount DevFS");
 
         // mount RamFS at /dev/shm
         let shm = devfs.root_inode().find(true, "shm").expect("cannot find shm");
@@ -1,21 +1,20 @@
 //! Implement INode for Pipe
 
-use crate::sync::{Event, EventBus, SpinNoIrqLock as Mutex};
-use crate::syscall::SysError::EAGAIN;
+use super::queue::ByteQueue;
+use crate::sync::SpinNoIrqLock as Mutex;
 use alloc::boxed::Box;
-use alloc::collections::BTreeSet;
-use alloc::{collections::vec_deque::VecDeque, sync::Arc};
+use alloc::sync::Arc;
 use core::any::Any;
-use core::cmp::min;
 use core::{
     future::Future,
-    mem::MaybeUninit,
     pin::Pin,
     task::{Context, Poll},
 };
-use rcore_fs::vfs::FsError::Again;
 use rcore_fs::vfs::*;
 
+/// Bytes a pipe holds before writers wait, as Linux
+const PIPE_CAPACITY: usize = 65536;
+
 #[derive(Clone, PartialEq)]
 pub enum PipeEnd {
     Read,
@@ -23,10 +22,8 @@ pub enum PipeEnd {
 }
 
 pub struct PipeData {
-    buf: VecDeque<u8>,
-    eventbus: EventBus,
-    /// number of pipe ends
-    end_cnt: i32,
+    /// closed once either end is
+    queue: ByteQueue,
 }
 
 #[derive(Clone)]
@@ -38,9 +35,7 @@ pub struct Pipe {
 impl Drop for Pipe {
     fn drop(&mut self) {
         // pipe end closed
-        let mut data = self.data.lock();
-        data.end_cnt -= 1;
-        data.eventbus.set(Event::CLOSED);
+        self.data.lock().queue.close();
     }
 }
 
@@ -48,9 +43,7 @@ impl Pipe {
     /// Create a pair of INode: (read, write)
     pub fn create_pair() -> (Pipe, Pipe) {
         let inner = PipeData {
-            buf: VecDeque::new(),
-            eventbus: EventBus::default(),
-            end_cnt: 2, // one read, one write
+            queue: ByteQueue::with_capacity(PIPE_CAPACITY),
         };
         let data = Arc::new(Mutex::new(inner));
         (
@@ -65,21 +58,20 @@ impl Pipe {
         )
     }
 
-    fn can_read(&self) -> bool {
-        if let PipeEnd::Read = self.direction {
-            // true
-            let data = self.data.lock();
-            data.buf.len() > 0 || data.end_cnt < 2 // other end closed
-        } else {
-            false
-        }
-    }
-
-    fn can_write(&self) -> bool {
-        if let PipeEnd::Write = self.direction {
-            self.data.lock().end_cnt == 2
-        } else {
-            false
+    /// Readiness of this end, `data` is the locked pipe data.
+    fn status(&self, data: &PipeData) -> PollStatus {
+        // the other end closed means end of file for reads, and writes
+        // which go nowhere
+        let status = data.queue.poll();
+        match self.direction {
+            PipeEnd::Read => PollStatus {
+                write: false,
+                ..status
+            },
+            PipeEnd::Write => PollStatus {
+                read: false,
+                ..status
+            },
         }
     }
 }
@@ -90,19 +82,7 @@ impl INode for Pipe {
             return Ok(0);
         }
         if let PipeEnd::Read = self.direction {
-            let mut data = self.data.lock();
-            if data.buf.len() == 0 && data.end_cnt == 2 {
-                Err(Again)
-            } else {
-                let len = min(buf.len(), data.buf.len());
-                for i in 0..len {
-                    buf[i] = data.buf.pop_front().unwrap();
-                }
-                if data.buf.len() == 0 {
-                    data.eventbus.clear(Event::READABLE);
-                }
-                Ok(len)
-            }
+            self.data.lock().queue.read(buf)
         } else {
             Ok(0)
         }
@@ -110,23 +90,15 @@ impl INode for Pipe {
 
     fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
         if let PipeEnd::Write = self.direction {
-            let mut data = self.data.lock();
-            for c in buf {
-                data.buf.push_back(*c);
-            }
-            data.eventbus.set(Event::READABLE);
-            Ok(buf.len())
+            // `Again` while full, sys_write waits for room
+            self.data.lock().queue.write(buf)
         } else {
             Ok(0)
         }
     }
 
     fn poll(&self) -> Result<PollStatus> {
-        Ok(PollStatus {
-            read: self.can_read(),
-            write: self.can_write(),
-            error: false,
-        })
+        Ok(self.status(&self.data.lock()))
     }
 
     fn async_poll<'a>(
@@ -141,17 +113,14 @@ impl INode for Pipe {
             type Output = Result<PollStatus>;
 
             fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-                if self.pipe.can_read() || self.pipe.can_write() {
-                    return Poll::Ready(self.pipe.poll());
-                }
-                let waker = cx.waker().clone();
+                // check and subscribe under one lock, so a write or close in
+                // between still wakes us
                 let mut data = self.pipe.data.lock();
-                data.eventbus.subscribe(Box::new({
-                    move |_| {
-                        waker.wake_by_ref();
-                        true
-                    }
-                }));
+                let status = self.pipe.status(&data);
+                if status.read || status.write {
+                    return Poll::Ready(Ok(status));
+                }
+                data.queue.subscribe(cx.waker().clone());
                 Poll::Pending
             }
         }
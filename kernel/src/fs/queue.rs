@@ -0,0 +1,389 @@
+//! Byte queue with waiters, shared by the tty and pipes
+//!
+//! The queue wakes its waiters whenever a read or a write which could not
+//! go on before can now, so owners only push and pop. It does no locking
+//! itself: checking for data and subscribing a waker must happen under the
+//! same lock of the owner, or a push in between is missed.
+//!
+//! Reads and writes never block here. They fail with `Again` instead, and
+//! a blocking caller subscribes and waits for the next wake, as the
+//! `async_poll` of the owner does. Blocking reads time out through the
+//! read timeout of the file, in `FileHandle::wait_ready`.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::task::Waker;
+use rcore_fs::vfs::{FsError, PollStatus, Result};
+
+pub struct ByteQueue {
+    buf: VecDeque<u8>,
+    /// most bytes queued at once
+    capacity: usize,
+    /// one side went away, see `close`
+    closed: bool,
+    /// tasks to wake at the next change, each once
+    waiters: Vec<Waker>,
+    /// sequence numbers of the bytes pushed by `push_numbered`, as runs of
+    /// the first number and the number of bytes
+    seqs: VecDeque<(usize, usize)>,
+}
+
+impl Default for ByteQueue {
+    /// An unbounded queue, for owners which bound their input themselves.
+    fn default() -> Self {
+        ByteQueue::with_capacity(usize::MAX)
+    }
+}
+
+impl ByteQueue {
+    /// A queue holding at most `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        ByteQueue {
+            buf: VecDeque::new(),
+            capacity,
+            closed: false,
+            waiters: Vec::new(),
+            seqs: VecDeque::new(),
+        }
+    }
+
+    /// Append as much of `bytes` as there is room for, waking readers.
+    /// Return the number of bytes taken. Once closed, all bytes are taken
+    /// and dropped, no one reads them.
+    pub fn push(&mut self, bytes: &[u8]) -> usize {
+        if self.closed {
+            return bytes.len();
+        }
+        let len = bytes.len().min(self.room());
+        if len == 0 {
+            return 0;
+        }
+        self.buf.extend(&bytes[..len]);
+        self.wake_all();
+        len
+    }
+
+    /// Append `bytes` numbered from `seq` on, see `next_seq` and `push`.
+    pub fn push_numbered(&mut self, bytes: &[u8], seq: usize) -> usize {
+        let len = bytes.len().min(self.room());
+        if self.closed || len == 0 {
+            return self.push(bytes);
+        }
+        match self.seqs.back_mut() {
+            Some((first, run)) if *first + *run == seq => *run += len,
+            _ => self.seqs.push_back((seq, len)),
+        }
+        self.push(&bytes[..len])
+    }
+
+    /// Sequence number of the next byte, if every byte queued was pushed
+    /// by `push_numbered`.
+    pub fn next_seq(&self) -> Option<usize> {
+        self.seqs.front().map(|&(first, _)| first)
+    }
+
+    /// Read into `buf` without blocking: `Again` while the queue is empty
+    /// and open, 0 at the end once it is empty and closed.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.buf.is_empty() && !self.closed {
+            return Err(FsError::Again);
+        }
+        Ok(self.pop_into(buf, &[]))
+    }
+
+    /// Write `bytes` without blocking: `Again` while the queue is full,
+    /// otherwise the number of bytes taken as by `push`.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+        if !bytes.is_empty() && self.room() == 0 && !self.closed {
+            return Err(FsError::Again);
+        }
+        Ok(self.push(bytes))
+    }
+
+    /// Whether `read` and `write` would go on without `Again`.
+    pub fn poll(&self) -> PollStatus {
+        PollStatus {
+            read: !self.buf.is_empty() || self.closed,
+            write: self.room() > 0 || self.closed,
+            error: false,
+        }
+    }
+
+    /// Move bytes into `buf`, return the number of bytes moved.
+    /// Stop after the first byte which is one of `delims`.
+    pub fn pop_into(&mut self, buf: &mut [u8], delims: &[u8]) -> usize {
+        let was_full = self.room() == 0;
+        let mut len = 0;
+        while len < buf.len() {
+            match self.buf.pop_front() {
+                Some(c) => {
+                    buf[len] = c;
+                    len += 1;
+                    if delims.contains(&c) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        self.forget_seqs(len);
+        if was_full && len > 0 {
+            // writers waiting for room
+            self.wake_all();
+        }
+        len
+    }
+
+    /// Return the next byte without consuming it.
+    pub fn front(&self) -> Option<u8> {
+        self.buf.front().cloned()
+    }
+
+    /// Copy bytes into `buf` without consuming them, return the number of
+    /// bytes copied.
+    pub fn peek(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for (dst, &c) in buf.iter_mut().zip(self.buf.iter()) {
+            *dst = c;
+            len += 1;
+        }
+        len
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Number of bytes which can be pushed before the queue is full.
+    pub fn room(&self) -> usize {
+        self.capacity.saturating_sub(self.buf.len())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Copy of the queued bytes, oldest first.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.buf.iter().cloned().collect()
+    }
+
+    /// Discard all queued bytes.
+    pub fn clear(&mut self) {
+        let was_full = self.room() == 0;
+        self.buf.clear();
+        self.seqs.clear();
+        if was_full {
+            self.wake_all();
+        }
+    }
+
+    /// Replace the queued bytes with as many of `bytes` as fit, waking
+    /// waiters.
+    pub fn replace(&mut self, bytes: &[u8]) {
+        self.buf.clear();
+        self.seqs.clear();
+        let len = bytes.len().min(self.capacity);
+        self.buf.extend(&bytes[..len]);
+        self.wake_all();
+    }
+
+    /// Tell every waiter one side of the queue went away. Reads then get
+    /// the rest and an end of file, writes are dropped.
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.wake_all();
+    }
+
+    /// Wake every waiter without changing the queue, they recheck their
+    /// own conditions.
+    pub fn wake_all(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Wake `waker` once at the next change. A task polled again before
+    /// that is only woken once.
+    pub fn subscribe(&mut self, waker: Waker) {
+        if !self.waiters.iter().any(|w| w.will_wake(&waker)) {
+            self.waiters.push(waker);
+        }
+    }
+
+    /// Drop the sequence numbers of `count` popped bytes.
+    fn forget_seqs(&mut self, mut count: usize) {
+        while count > 0 {
+            let (first, len) = match self.seqs.front_mut() {
+                Some(run) => run,
+                None => break,
+            };
+            if *len > count {
+                *first += count;
+                *len -= count;
+                break;
+            }
+            count -= *len;
+            self.seqs.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{RawWaker, RawWakerVTable};
+
+    // a waker counting its wakes in an `Arc<AtomicUsize>`
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+    unsafe fn clone_waker(data: *const ()) -> RawWaker {
+        let count = Arc::from_raw(data as *const AtomicUsize);
+        core::mem::forget(count.clone());
+        core::mem::forget(count);
+        RawWaker::new(data, &VTABLE)
+    }
+
+    unsafe fn wake(data: *const ()) {
+        wake_by_ref(data);
+        drop_waker(data);
+    }
+
+    unsafe fn wake_by_ref(data: *const ()) {
+        (*(data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe fn drop_waker(data: *const ()) {
+        drop(Arc::from_raw(data as *const AtomicUsize));
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, Waker) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let data = Arc::into_raw(count.clone()) as *const ();
+        let waker = unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) };
+        (count, waker)
+    }
+
+    #[test]
+    fn push_stops_at_capacity() {
+        let mut queue = ByteQueue::with_capacity(4);
+        assert_eq!(queue.push(b"abc"), 3);
+        assert_eq!(queue.push(b"def"), 1);
+        assert_eq!(queue.room(), 0);
+        assert!(matches!(queue.write(b"g"), Err(FsError::Again)));
+        assert!(!queue.poll().write);
+        assert_eq!(queue.to_vec(), b"abcd");
+    }
+
+    #[test]
+    fn read_again_then_end_of_file() {
+        let mut queue = ByteQueue::with_capacity(8);
+        let mut buf = [0u8; 8];
+        assert!(matches!(queue.read(&mut buf), Err(FsError::Again)));
+        queue.push(b"hi");
+        queue.close();
+        assert!(matches!(queue.read(&mut buf), Ok(2)));
+        assert_eq!(&buf[..2], b"hi");
+        assert!(matches!(queue.read(&mut buf), Ok(0)));
+        assert!(queue.poll().read);
+    }
+
+    #[test]
+    fn writes_after_close_are_dropped() {
+        let mut queue = ByteQueue::with_capacity(2);
+        queue.push(b"ab");
+        queue.close();
+        assert!(matches!(queue.write(b"cd"), Ok(2)));
+        assert_eq!(queue.to_vec(), b"ab");
+    }
+
+    #[test]
+    fn push_wakes_each_waiter_once() {
+        let mut queue = ByteQueue::default();
+        let (reader, waker) = counter();
+        queue.subscribe(waker.clone());
+        queue.subscribe(waker);
+        queue.push(b"x");
+        queue.push(b"y");
+        assert_eq!(reader.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn reading_a_full_queue_wakes_writers() {
+        let mut queue = ByteQueue::with_capacity(2);
+        queue.push(b"ab");
+        let (writer, waker) = counter();
+        queue.subscribe(waker);
+        let mut buf = [0u8; 1];
+        queue.pop_into(&mut buf, &[]);
+        assert_eq!(writer.load(Ordering::SeqCst), 1);
+        assert!(queue.poll().write);
+    }
+
+    #[test]
+    fn close_and_wake_all_wake_waiters() {
+        let mut queue = ByteQueue::default();
+        let (waiter, waker) = counter();
+        queue.subscribe(waker.clone());
+        queue.wake_all();
+        assert_eq!(waiter.load(Ordering::SeqCst), 1);
+        queue.subscribe(waker);
+        queue.close();
+        assert_eq!(waiter.load(Ordering::SeqCst), 2);
+        assert!(queue.is_closed());
+    }
+
+    #[test]
+    fn numbered_bytes_keep_their_numbers() {
+        let mut queue = ByteQueue::with_capacity(3);
+        assert_eq!(queue.push_numbered(b"ab", 10), 2);
+        assert_eq!(queue.push_numbered(b"cd", 12), 1);
+        let mut buf = [0u8; 1];
+        queue.pop_into(&mut buf, &[]);
+        assert_eq!(queue.next_seq(), Some(11));
+    }
+
+    #[test]
+    fn pop_stops_after_a_delimiter() {
+        let mut queue = ByteQueue::default();
+        queue.push(b"ab\ncd");
+        let mut buf = [0u8; 8];
+        assert_eq!(queue.pop_into(&mut buf, b"\n"), 3);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn a_push_after_subscribing_wakes_the_reader() {
+        // what a pipe poll does under its lock: check, then subscribe
+        let mut queue = ByteQueue::with_capacity(4);
+        assert!(!queue.poll().read);
+        let (reader, waker) = counter();
+        queue.subscribe(waker);
+        queue.push(b"z");
+        assert_eq!(reader.load(Ordering::SeqCst), 1);
+        assert!(queue.poll().read);
+    }
+
+    #[test]
+    fn close_wakes_every_waiting_reader() {
+        let mut queue = ByteQueue::with_capacity(4);
+        let (first, waker) = counter();
+        queue.subscribe(waker);
+        let (second, waker) = counter();
+        queue.subscribe(waker);
+        queue.close();
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+        let mut buf = [0u8; 4];
+        assert!(matches!(queue.read(&mut buf), Ok(0)));
+    }
+}
@@ -3,15 +3,23 @@
 use crate::backtrace;
 use core::alloc::Layout;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 use log::*;
 
 #[lang = "eh_personality"]
 extern "C" fn eh_personality() {}
 
+/// Set once device states are dumped, so a panic while dumping them does
+/// not dump again
+static DEVICES_DUMPED: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     error!("\n\n{}", info);
     backtrace::backtrace();
+    if !DEVICES_DUMPED.swap(true, Ordering::SeqCst) {
+        crate::fs::dump_device_state();
+    }
     loop {
         crate::arch::cpu::halt()
     }
@@ -1,12 +1,141 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use lazy_static::lazy_static;
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
 use crate::sync::SpinNoIrqLock as Mutex;
+use crate::util::text::LineAssembler;
+use rcore_fs::vfs::{FsError, INode, Result};
 
 lazy_static! {
     static ref LOG_LOCK: Mutex<()> = Mutex::new(());
+    /// Puts the output copied to the log file back into lines, so the file
+    /// only gets whole lines. A partial line held when the file changes
+    /// goes to the old file as it is. Held while output is taken for a file,
+    /// so it is written in the order it was printed.
+    static ref LOG_LINES: Mutex<LineAssembler> = Mutex::new(LineAssembler::new(LOG_LINE_MAX));
+}
+
+/// Lines longer than this are split in the log file
+const LOG_LINE_MAX: usize = 1024;
+
+/// Id + 1 of the CPU holding `LOG_LOCK`, 0 if none
+static LOG_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+/// File console output is copied to, with the offset to write next at.
+/// The offset grows as output is taken for the file, before it is written
+static LOG_FILE: Mutex<Option<(Arc<dyn INode>, usize)>> = Mutex::new(None);
+
+/// Output not yet copied to `LOG_FILE`. Printing happens with interrupts
+/// off, also in interrupt context, where the file cannot be written, so
+/// `flush_log_file` writes it later.
+static LOG_PENDING: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Output past this waiting for the log file is dropped
+const LOG_PENDING_MAX: usize = 0x10000;
+
+/// Bytes of output dropped as `LOG_PENDING` was full
+static LOG_DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Set while a CPU writes `LOG_PENDING` to the file
+static LOG_FLUSHING: AtomicBool = AtomicBool::new(false);
+
+/// Copy console output to the end of `file` from now on, or stop if `None`.
+/// The copy stops by itself at the first write error.
+pub fn set_log_file(file: Option<Arc<dyn INode>>) -> Result<()> {
+    let file = match file {
+        Some(file) => {
+            let size = file.metadata()?.size;
+            Some((file, size))
+        }
+        None => None,
+    };
+    let (old, text) = {
+        let mut lines = LOG_LINES.lock();
+        // output printed up to the swap goes to the file it was copied for,
+        // output printed after it to the new one
+        let (old, pending) = {
+            let mut pending = LOG_PENDING.lock();
+            let old = core::mem::replace(&mut *LOG_FILE.lock(), file);
+            (old, core::mem::take(&mut *pending))
+        };
+        let mut text = log_lines(&mut lines, &pending);
+        lines.flush(|line| text.extend_from_slice(line));
+        (old, text)
+    };
+    if let Some((old, offset)) = old {
+        write_log_file(old, offset, &text);
+    }
+    Ok(())
+}
+
+/// Write the output waiting for the log file to it. Called from process
+/// context with no lock held, as writing the file may block or print.
+pub fn flush_log_file() {
+    if LOG_FLUSHING.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let log_file = {
+        let mut lines = LOG_LINES.lock();
+        let pending = core::mem::take(&mut *LOG_PENDING.lock());
+        let text = log_lines(&mut lines, &pending);
+        let mut log_file = LOG_FILE.lock();
+        log_file.as_mut().map(|(file, offset)| {
+            let at = *offset;
+            *offset += text.len();
+            (file.clone(), at, text)
+        })
+    };
+    if let Some((file, offset, text)) = log_file {
+        write_log_file(file, offset, &text);
+    }
+    LOG_FLUSHING.store(false, Ordering::Release);
+}
+
+/// Put `pending` output into whole lines for the log file, noting output
+/// dropped meanwhile. A partial line waits in `lines` for the rest.
+fn log_lines(lines: &mut LineAssembler, pending: &[u8]) -> Vec<u8> {
+    let mut text = Vec::new();
+    lines.push(pending, |line| {
+        text.extend_from_slice(line);
+        text.push(b'\n');
+    });
+    let dropped = LOG_DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let note = format!("[log file copy dropped {} bytes]\n", dropped);
+        text.extend_from_slice(note.as_bytes());
+    }
+    text
+}
+
+/// Write `text` taken for `file` at `offset`, where its room was kept.
+fn write_log_file(file: Arc<dyn INode>, offset: usize, text: &[u8]) {
+    if text.is_empty() {
+        return;
+    }
+    let error = match file.write_at(offset, text) {
+        // a short write means the file system is full
+        Ok(len) if len < text.len() => FsError::NoDeviceSpace,
+        Ok(_) => return,
+        Err(err) => err,
+    };
+    {
+        let mut log_file = LOG_FILE.lock();
+        // the file may have been changed while it was written
+        if let Some((current, _)) = log_file.as_ref() {
+            if Arc::ptr_eq(current, &file) {
+                *log_file = None;
+            }
+        }
+    }
+    // the console already has the output, only the copy stops
+    print(format_args!(
+        "[log file write failed: {:?}, stopped copying]\n",
+        error
+    ));
 }
 
 pub fn init() {
@@ -43,15 +172,44 @@ macro_rules! with_color {
 }
 
 fn print_in_color(args: fmt::Arguments, color_code: u8) {
-    use crate::arch::io;
-    let _guard = LOG_LOCK.lock();
-    io::putfmt(with_color!(args, color_code));
+    putfmt_locked(with_color!(args, color_code));
 }
 
 pub fn print(args: fmt::Arguments) {
+    putfmt_locked(args);
+}
+
+/// Print under `LOG_LOCK`, so messages of different CPUs do not mix.
+///
+/// A serial or console driver printing or logging from inside `putfmt`
+/// would spin on `LOG_LOCK` held by its own CPU forever. Such nested
+/// messages are dropped instead. The lock disables interrupts, so only
+/// the CPU holding it can see itself as the owner.
+fn putfmt_locked(args: fmt::Arguments) {
     use crate::arch::io;
+    let cpu = crate::arch::cpu::id() + 1;
+    if LOG_OWNER.load(Ordering::Relaxed) == cpu {
+        return;
+    }
     let _guard = LOG_LOCK.lock();
+    LOG_OWNER.store(cpu, Ordering::Relaxed);
     io::putfmt(args);
+    queue_log_file(args);
+    LOG_OWNER.store(0, Ordering::Relaxed);
+}
+
+/// Keep `args` for `flush_log_file` to copy to the log file, if one is set.
+fn queue_log_file(args: fmt::Arguments) {
+    if LOG_FILE.lock().is_none() {
+        return;
+    }
+    let text = format!("{}", args);
+    let mut pending = LOG_PENDING.lock();
+    if pending.len() + text.len() > LOG_PENDING_MAX {
+        LOG_DROPPED.fetch_add(text.len(), Ordering::Relaxed);
+        return;
+    }
+    pending.extend_from_slice(text.as_bytes());
 }
 
 struct SimpleLogger;
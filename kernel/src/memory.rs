@@ -4,10 +4,13 @@ use super::HEAP_ALLOCATOR;
 use crate::consts::{KERNEL_OFFSET, MEMORY_OFFSET, PHYSICAL_MEMORY_OFFSET};
 use crate::process::current_thread;
 use crate::sync::SpinNoIrqLock;
+use alloc::string::String;
 use bitmap_allocator::BitAlloc;
 use buddy_system_allocator::Heap;
 use core::mem;
 use core::mem::size_of;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use log::*;
 use rcore_memory::*;
 
@@ -30,6 +33,45 @@ pub type FrameAlloc = bitmap_allocator::BitAlloc1M;
 
 pub static FRAME_ALLOCATOR: SpinNoIrqLock<FrameAlloc> = SpinNoIrqLock::new(FrameAlloc::DEFAULT);
 
+/// Frames given to `FRAME_ALLOCATOR`, and allocated from it, for /proc/meminfo
+static FRAMES_TOTAL: AtomicUsize = AtomicUsize::new(0);
+static FRAMES_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Give the frames in `range` to the frame allocator `ba`, the locked
+/// `FRAME_ALLOCATOR`.
+pub fn insert_frames(ba: &mut FrameAlloc, range: Range<usize>) {
+    FRAMES_TOTAL.fetch_add(range.len(), Ordering::Relaxed);
+    ba.insert(range);
+}
+
+/// Text of /proc/meminfo: physical memory and kernel heap usage. Frames
+/// the heap grew by count as used memory and as heap.
+pub fn meminfo_report() -> String {
+    let (heap_total, heap_used, heap_requested) = {
+        // formatting allocates, so not under the heap lock
+        let heap = HEAP_ALLOCATOR.lock();
+        (
+            heap.stats_total_bytes(),
+            heap.stats_alloc_actual(),
+            heap.stats_alloc_user(),
+        )
+    };
+    let total = FRAMES_TOTAL.load(Ordering::Relaxed);
+    let allocated = FRAMES_ALLOCATED.load(Ordering::Relaxed);
+    let lines = [
+        ("MemTotal", total * PAGE_SIZE),
+        ("MemFree", total.saturating_sub(allocated) * PAGE_SIZE),
+        ("HeapTotal", heap_total),
+        ("HeapUsed", heap_used),
+        ("HeapRequested", heap_requested),
+    ];
+    let mut report = String::new();
+    for (name, bytes) in lines.iter() {
+        report += &format!("{:<16}{:>8} kB\n", format!("{}:", name), bytes / 1024);
+    }
+    report
+}
+
 /// Convert physical address to virtual address
 #[inline]
 #[cfg(not(mipsel))]
@@ -70,6 +112,9 @@ impl FrameAllocator for GlobalFrameAlloc {
             .lock()
             .alloc()
             .map(|id| id * PAGE_SIZE + MEMORY_OFFSET);
+        if ret.is_some() {
+            FRAMES_ALLOCATED.fetch_add(1, Ordering::Relaxed);
+        }
         trace!("Allocate frame: {:x?}", ret);
         ret
         // TODO: try to swap out when alloc failed
@@ -80,6 +125,9 @@ impl FrameAllocator for GlobalFrameAlloc {
             .lock()
             .alloc_contiguous(size, align_log2)
             .map(|id| id * PAGE_SIZE + MEMORY_OFFSET);
+        if ret.is_some() {
+            FRAMES_ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        }
         trace!("Allocate frame: {:x?}", ret);
         ret
         // TODO: try to swap out when alloc failed
@@ -89,6 +137,7 @@ impl FrameAllocator for GlobalFrameAlloc {
         FRAME_ALLOCATOR
             .lock()
             .dealloc((target - MEMORY_OFFSET) / PAGE_SIZE);
+        FRAMES_ALLOCATED.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -669,7 +669,8 @@ impl Socket for RawSocketState {
 
                     Ok(len)
                 } else {
-                    unimplemented!("ip type")
+                    // only IPv4 headers are filled in
+                    Err(SysError::EAFNOSUPPORT)
                 }
             } else {
                 Err(SysError::ENOTCONN)
@@ -711,7 +712,8 @@ impl PacketSocketState {
 
 impl Socket for PacketSocketState {
     fn read(&self, _data: &mut [u8]) -> (SysResult, Endpoint) {
-        unimplemented!()
+        // receiving link level packets is not supported yet
+        (Err(SysError::ENOSYS), Endpoint::Ip(IpEndpoint::UNSPECIFIED))
     }
 
     fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult {
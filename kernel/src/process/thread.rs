@@ -12,7 +12,7 @@ use crate::arch::{
     memory::{get_page_fault_addr, set_page_table},
     paging::*,
 };
-use crate::drivers::IRQ_MANAGER;
+use crate::drivers::{serial::SERIAL_ACTIVITY, IRQ_MANAGER, SOCKET_ACTIVITY};
 use crate::fs::{FileHandle, FileLike, OpenOptions, FOLLOW_MAX_DEPTH};
 use crate::ipc::{SemProc, ShmProc};
 use crate::memory::{
@@ -23,6 +23,7 @@ use crate::sync::{EventBus, SpinLock, SpinNoIrqLock as Mutex};
 use crate::{
     signal::{handle_signal, Siginfo, Signal, SignalAction, SignalStack, Sigset},
     syscall::handle_syscall,
+    trap::TICK_ACTIVITY,
 };
 use alloc::{
     boxed::Box, collections::BTreeMap, collections::VecDeque, string::String, sync::Arc,
@@ -273,21 +274,22 @@ impl Thread {
                 false,
             )),
         );
-        files.insert(
-            2,
-            FileLike::File(FileHandle::new(
-                crate::fs::TTY.clone(),
-                OpenOptions {
-                    read: false,
-                    write: true,
-                    append: false,
-                    nonblock: false,
-                },
-                String::from("/dev/tty"),
-                false,
-                false,
-            )),
+        let stderr = FileHandle::new(
+            crate::fs::TTY.clone(),
+            OpenOptions {
+                read: false,
+                write: true,
+                append: false,
+                nonblock: false,
+            },
+            String::from("/dev/tty"),
+            false,
+            false,
         );
+        // stderr shares the tty output buffer with stdout, flushing it
+        // shows errors at once and after the output written before
+        stderr.set_sync_writes(true);
+        files.insert(2, FileLike::File(stderr));
 
         // user context
         let mut context = UserContext::default();
@@ -581,6 +583,7 @@ pub fn spawn(thread: Arc<Thread>) {
             thread.end_running(thread_context);
             if exit {
                 info!("thread {} stopped", thread.tid);
+                remove_waiters(thread.tid);
                 break;
             } else if do_yield {
                 yield_now().await;
@@ -591,6 +594,16 @@ pub fn spawn(thread: Arc<Thread>) {
     spawn_thread(Box::pin(future), vmtoken, temp);
 }
 
+/// Drop the condvar waiter entries of a stopped thread. They would keep
+/// its process alive, and notifying them looks for its closed files.
+fn remove_waiters(tid: Tid) {
+    for condvar in &[&*TICK_ACTIVITY, &*SOCKET_ACTIVITY, &*SERIAL_ACTIVITY] {
+        if condvar.remove_waiter(tid) {
+            info!("removed waiter of stopped thread {}", tid);
+        }
+    }
+}
+
 fn spawn_thread(
     future: Pin<Box<dyn Future<Output = ()> + Send + 'static>>,
     vmtoken: usize,
@@ -5,6 +5,12 @@ use crate::syscall::TimeSpec;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Waiting longer than this many ticks is reported as a possible lost wakeup
+#[cfg(debug_assertions)]
+const WAIT_WARN_TICKS: usize = 1000;
 
 pub struct RegisteredProcess {
     proc: Arc<SpinNoIrqLock<Process>>,
@@ -17,6 +23,29 @@ pub struct RegisteredProcess {
 pub struct Condvar {
     wait_queue: SpinNoIrqLock<VecDeque<Arc<Thread>>>,
     pub epoll_queue: SpinNoIrqLock<VecDeque<RegisteredProcess>>,
+    #[cfg(debug_assertions)]
+    stats: DebugCounters,
+}
+
+/// Counters kept in debug builds to track down lost wakeups
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct DebugCounters {
+    waiters: AtomicUsize,
+    notifies: AtomicUsize,
+    empty_notifies: AtomicUsize,
+}
+
+/// Snapshot of the debug counters of a condvar
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, Copy)]
+pub struct CondvarStats {
+    /// threads waiting right now
+    pub waiters: usize,
+    /// notify calls so far
+    pub notifies: usize,
+    /// notify calls which found no waiter
+    pub empty_notifies: usize,
 }
 
 impl Condvar {
@@ -28,6 +57,38 @@ impl Condvar {
         self.wait_queue.lock().len()
     }
 
+    #[cfg(debug_assertions)]
+    pub fn debug_stats(&self) -> CondvarStats {
+        CondvarStats {
+            waiters: self.stats.waiters.load(Ordering::Relaxed),
+            notifies: self.stats.notifies.load(Ordering::Relaxed),
+            empty_notifies: self.stats.empty_notifies.load(Ordering::Relaxed),
+        }
+    }
+
+    fn debug_wait_begin(&self) {
+        #[cfg(debug_assertions)]
+        self.stats.waiters.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn debug_wait_end(&self) {
+        #[cfg(debug_assertions)]
+        self.stats.waiters.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn debug_notify(&self) {
+        #[cfg(debug_assertions)]
+        {
+            self.stats.notifies.fetch_add(1, Ordering::Relaxed);
+            if self.stats.waiters.load(Ordering::Relaxed) == 0 {
+                self.stats.empty_notifies.fetch_add(1, Ordering::Relaxed);
+                // harmless by itself, but a waiter showing up right after
+                // this has missed its wakeup
+                debug!("condvar {:p} notified without waiters", self);
+            }
+        }
+    }
+
     /// Park current thread and wait for this condvar to be notified.
     #[deprecated(note = "this may leads to lost wakeup problem. please use `wait` instead.")]
     pub fn _wait(&self) {
@@ -60,7 +121,10 @@ impl Condvar {
         for condvar in condvars {
             let mut lock = condvar.wait_queue.lock();
             //lock.push_back(token.clone());
+            condvar.debug_wait_begin();
         }
+        #[cfg(debug_assertions)]
+        let (begin, mut warned) = (unsafe { crate::trap::wall_tick() }, false);
         let mut locks = Vec::with_capacity(condvars.len());
         loop {
             for condvar in condvars {
@@ -73,8 +137,19 @@ impl Condvar {
             if let Some(res) = condition() {
                 let _ = FlagsGuard::no_irq_region();
                 //thread_manager().cancel_sleeping(tid);
+                for condvar in condvars {
+                    condvar.debug_wait_end();
+                }
                 return res;
             } else {
+                #[cfg(debug_assertions)]
+                {
+                    let waited = unsafe { crate::trap::wall_tick() } - begin;
+                    if !warned && waited > WAIT_WARN_TICKS {
+                        warn!("waited {} ticks on condvars, lost wakeup?", waited);
+                        warned = true;
+                    }
+                }
                 for condvar in condvars {
                     let mut queue = condvar.wait_queue.lock();
                     //if queue.iter().find(|&t| Arc::ptr_eq(t, &token)).is_none() {
@@ -95,6 +170,7 @@ impl Condvar {
         //let token = Arc::new(thread::current());
         let mut lock = self.wait_queue.lock();
         //lock.push_back(token.clone());
+        self.debug_wait_begin();
 
         //thread::park_action(move || {
         //drop(lock);
@@ -102,6 +178,7 @@ impl Condvar {
         //});
         // let mut lock = self.wait_queue.lock();
         // lock.retain(|t| !Arc::ptr_eq(&t, &token));
+        self.debug_wait_end();
         mutex.lock()
     }
 
@@ -118,6 +195,7 @@ impl Condvar {
         //let token = Arc::new(thread::current());
         let mut lock = self.wait_queue.lock();
         //lock.push_back(token.clone());
+        self.debug_wait_begin();
         drop(lock);
         drop(guard);
 
@@ -128,6 +206,7 @@ impl Condvar {
         }
         // let mut lock = self.wait_queue.lock();
         // lock.retain(|t| !Arc::ptr_eq(&t, &token));
+        self.debug_wait_end();
         let end = crate::trap::uptime_msec();
         if end - begin >= timeout.as_millis() as usize {
             None
@@ -137,6 +216,7 @@ impl Condvar {
     }
 
     pub fn notify_one(&self) {
+        self.debug_notify();
         let mut queue = self.wait_queue.lock();
         if let Some(t) = queue.front() {
             self.epoll_callback(t);
@@ -147,6 +227,7 @@ impl Condvar {
     }
 
     pub fn notify_all(&self) {
+        self.debug_notify();
         let mut queue = self.wait_queue.lock();
         for t in queue.iter() {
             self.epoll_callback(t);
@@ -158,6 +239,7 @@ impl Condvar {
     /// Notify up to `n` waiters.
     /// Return the number of waiters that were woken up.
     pub fn notify_n(&self, n: usize) -> usize {
+        self.debug_notify();
         let mut count = 0;
         let mut queue = self.wait_queue.lock();
         for t in queue.iter() {
@@ -189,6 +271,22 @@ impl Condvar {
         });
     }
 
+    /// Drop every waiter entry of thread `tid`, which will never wait
+    /// again. Return whether there was one.
+    pub fn remove_waiter(&self, tid: usize) -> bool {
+        let mut queue = self.wait_queue.lock();
+        let len = queue.len();
+        queue.retain(|t| t.tid != tid);
+        let mut removed = queue.len() != len;
+        drop(queue);
+
+        let mut epoll_list = self.epoll_queue.lock();
+        let len = epoll_list.len();
+        epoll_list.retain(|ist| ist.tid != tid);
+        removed |= epoll_list.len() != len;
+        removed
+    }
+
     pub fn unregister_epoll_list(&self, tid: usize, epfd: usize, fd: usize) -> bool {
         let mut epoll_list = self.epoll_queue.lock();
         for idx in 0..epoll_list.len() {
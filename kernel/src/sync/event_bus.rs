@@ -33,7 +33,9 @@ pub type EventHandler = Box<dyn Fn(Event) -> bool + Send>;
 #[derive(Default)]
 pub struct EventBus {
     event: Event,
-    callbacks: Vec<EventHandler>,
+    /// each with the id `subscribe` returned for it
+    callbacks: Vec<(usize, EventHandler)>,
+    next_id: usize,
 }
 
 impl EventBus {
@@ -56,12 +58,23 @@ impl EventBus {
         new.insert(set);
         self.event = new;
         if new != orig {
-            self.callbacks.retain(|f| !f(new));
+            self.callbacks.retain(|(_, f)| !f(new));
         }
     }
 
-    pub fn subscribe(&mut self, callback: EventHandler) {
-        self.callbacks.push(callback);
+    /// Call `callback` on every change of the events until it returns
+    /// true. Return an id to `unsubscribe` it with.
+    pub fn subscribe(&mut self, callback: EventHandler) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.callbacks.push((id, callback));
+        id
+    }
+
+    /// Drop the callback `subscribe` returned `id` for, if it was not
+    /// dropped yet.
+    pub fn unsubscribe(&mut self, id: usize) {
+        self.callbacks.retain(|(i, _)| *i != id);
     }
 
     pub fn get_callback_len(&self) -> usize {
@@ -11,7 +11,7 @@ use rcore_fs::vfs::Timespec;
 use crate::drivers::SOCKET_ACTIVITY;
 use crate::fs::*;
 use crate::memory::MemorySet;
-use crate::sync::Condvar;
+use crate::sync::{Condvar, EventBus, SpinNoIrqLock};
 use crate::trap::TICK_ACTIVITY;
 use alloc::boxed::Box;
 use core::future::Future;
@@ -37,21 +37,50 @@ impl Syscall<'_> {
         }
         let slice = unsafe { self.vm().check_write_array(base.ptr(), len)? };
 
-        let file_like = proc.get_file_like(fd)?;
-        let len = file_like.read(slice).await?;
+        let mut file_like = proc.get_file_like(fd)?.clone();
+        let pgid = proc.pgid;
+        let eventbus = proc.eventbus.clone();
+        // the read may block, release the process meanwhile
+        drop(proc);
+        file_like.check_foreground(pgid)?;
+        let len = file_like.read(slice, &eventbus).await?;
         Ok(len)
     }
 
-    pub fn sys_write(&mut self, fd: usize, base: *const u8, len: usize) -> SysResult {
+    pub async fn sys_write(&mut self, fd: usize, base: *const u8, len: usize) -> SysResult {
         let mut proc = self.process();
         if !proc.pid.is_init() {
             //we trust pid 0 process
             info!("write: fd: {}, base: {:?}, len: {:#x}", fd, base, len);
         }
         let slice = unsafe { self.vm().check_read_array(base, len)? };
-        let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(slice)?;
-        Ok(len)
+        let file_like = proc.get_file_like(fd)?.clone();
+        let eventbus = proc.eventbus.clone();
+        drop(proc);
+        self.write_waiting(file_like, slice, &eventbus).await
+    }
+
+    /// Write `buf` to `file_like`, sleeping while it is a tty holding output
+    /// back behind its output rate limit, or waiting while it is a full
+    /// pipe. `eventbus` is the event bus of the process, to end the wait on
+    /// signals.
+    async fn write_waiting(
+        &mut self,
+        mut file_like: FileLike,
+        buf: &[u8],
+        eventbus: &Arc<SpinNoIrqLock<EventBus>>,
+    ) -> SysResult {
+        loop {
+            match file_like.write(buf) {
+                Err(SysError::EAGAIN) => match file_like.output_wait() {
+                    Some(wait) => {
+                        self.sleep_for(wait).await?;
+                    }
+                    None => file_like.wait_writable(eventbus).await?,
+                },
+                ret => return ret,
+            }
+        }
     }
 
     pub async fn sys_pread(
@@ -67,7 +96,12 @@ impl Syscall<'_> {
         );
         let mut proc = self.process();
         let slice = unsafe { self.vm().check_write_array(base.ptr(), len)? };
-        let len = proc.get_file(fd)?.read_at(offset, slice).await?;
+        let file = proc.get_file(fd)?.clone();
+        let pgid = proc.pgid;
+        let eventbus = proc.eventbus.clone();
+        drop(proc);
+        file.check_foreground(pgid)?;
+        let len = file.read_at(offset, slice, &eventbus).await?;
         Ok(len)
     }
 
@@ -413,15 +447,11 @@ impl Syscall<'_> {
                         FileLike::Socket(_socket) => {
                             &(*crate::drivers::SOCKET_ACTIVITY).register_epoll_list(
                                 self.thread.proc.clone(),
-                                0,
-                                //thread::current().id(),
+                                self.thread.tid,
                                 epfd,
                                 *fd,
                             );
-                            callbacks.push((
-                                1, 0, //thread::current().id(),
-                                epfd, *fd,
-                            ));
+                            callbacks.push((1, self.thread.tid, epfd, *fd));
                         }
                         FileLike::EpollInstance(_) => {
                             return Err(SysError::EINVAL);
@@ -542,15 +572,24 @@ impl Syscall<'_> {
             unsafe { IoVecs::check_and_new(iov_ptr.ptr(), iov_count, &self.vm(), true)? };
 
         // read all data to a buf
-        let file_like = proc.get_file_like(fd)?;
+        let mut file_like = proc.get_file_like(fd)?.clone();
+        let pgid = proc.pgid;
+        let eventbus = proc.eventbus.clone();
+        drop(proc);
+        file_like.check_foreground(pgid)?;
         let mut buf = iovs.new_buf(true);
-        let len = file_like.read(buf.as_mut_slice()).await?;
+        let len = file_like.read(buf.as_mut_slice(), &eventbus).await?;
         // copy data to user
         iovs.write_all_from_slice(&buf[..len]);
         Ok(len)
     }
 
-    pub fn sys_writev(&mut self, fd: usize, iov_ptr: *const IoVec, iov_count: usize) -> SysResult {
+    pub async fn sys_writev(
+        &mut self,
+        fd: usize,
+        iov_ptr: *const IoVec,
+        iov_count: usize,
+    ) -> SysResult {
         let mut proc = self.process();
         if !proc.pid.is_init() {
             // we trust pid 0 process
@@ -567,9 +606,10 @@ impl Syscall<'_> {
         let iovs = unsafe { IoVecs::check_and_new(iov_ptr, iov_count, &self.vm(), false)? };
 
         let buf = iovs.read_all_to_vec();
-        let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(buf.as_slice())?;
-        Ok(len)
+        let file_like = proc.get_file_like(fd)?.clone();
+        let eventbus = proc.eventbus.clone();
+        drop(proc);
+        self.write_waiting(file_like, &buf, &eventbus).await
     }
 
     pub fn sys_open(&mut self, path: *const u8, flags: usize, mode: usize) -> SysResult {
@@ -626,6 +666,13 @@ impl Syscall<'_> {
             false,
             flags.contains(OpenFlags::CLOEXEC),
         );
+        // O_EXCL without O_CREAT on a tty takes exclusive output of it
+        if flags.contains(OpenFlags::EXCLUSIVE) && !flags.contains(OpenFlags::CREATE) {
+            match file.claim_exclusive() {
+                Ok(()) | Err(FsError::NotSupported) => {}
+                Err(err) => return Err(SysError::from(err)),
+            }
+        }
 
         // for debugging
         if cfg!(debug_assertions) {
@@ -879,7 +926,7 @@ impl Syscall<'_> {
         self.dup_impl(fd1, fd2, flags)
     }
 
-    pub fn sys_ioctl(
+    pub async fn sys_ioctl(
         &mut self,
         fd: usize,
         request: usize,
@@ -892,6 +939,34 @@ impl Syscall<'_> {
             fd, request, arg1, arg2, arg3
         );
         use crate::fs::ioctl::*;
+        // devices dereference the argument directly, check it covers
+        // the whole structure of the request first
+        let arg_size = match ioctl_arg(request) {
+            Some(IoctlArg::In(size)) => unsafe {
+                self.vm()
+                    .check_read_array(arg1 as *const u8, size)
+                    .map_err(|_| FsError::IOCTLError)?;
+                size
+            },
+            Some(IoctlArg::Out(size)) => unsafe {
+                self.vm()
+                    .check_write_array(arg1 as *mut u8, size)
+                    .map_err(|_| FsError::IOCTLError)?;
+                size
+            },
+            Some(IoctlArg::InOut(size)) => unsafe {
+                let vm = self.vm();
+                vm.check_read_array(arg1 as *const u8, size)
+                    .map_err(|_| FsError::IOCTLError)?;
+                vm.check_write_array(arg1 as *mut u8, size)
+                    .map_err(|_| FsError::IOCTLError)?;
+                size
+            },
+            None => 0,
+        };
+        if let Some(buf) = ioctl_buf(request) {
+            return self.ioctl_with_buf(fd, request, arg1, arg_size, buf);
+        }
         match request {
             FIOCLEX => self.sys_fcntl(fd, F_SETFD, FD_CLOEXEC),
             FIONCLEX => self.sys_fcntl(fd, F_SETFD, 0),
@@ -904,6 +979,68 @@ impl Syscall<'_> {
                     self.sys_fcntl(fd, F_SETFD, O_NONBLOCK)
                 }
             }
+            TIOCSLOGFD => {
+                // the fd is resolved here, the tty does not look into the
+                // process
+                let log_fd = unsafe { *(arg1 as *const i32) };
+                // setting the file writes output to the old one, the process
+                // is not locked meanwhile
+                let (file, log_file) = {
+                    let mut proc = self.process();
+                    let log_file = if log_fd < 0 {
+                        None
+                    } else {
+                        let file = proc
+                            .get_file(log_fd as usize)
+                            .map_err(|_| SysError::EINVAL)?;
+                        Some(file.inode())
+                    };
+                    (proc.get_file(fd)?.clone(), log_file)
+                };
+                file.set_log_file(log_file)?;
+                Ok(0)
+            }
+            FIOWAITEVENT => {
+                // the wait may block, the process is not locked meanwhile
+                let (file, eventbus) = {
+                    let mut proc = self.process();
+                    (proc.get_file(fd)?.clone(), proc.eventbus.clone())
+                };
+                let event = file.wait_event(&eventbus).await?;
+                Ok(event)
+            }
+            TCSETSW | TCSETSF | TIOCDRAIN | TIOCSBAUD => {
+                // the tty fails these with EAGAIN until its output is
+                // drained, sleep a tick between tries without the process
+                // locked
+                loop {
+                    let ret = {
+                        let mut proc = self.process();
+                        proc.get_file_like(fd)?.ioctl(request, arg1, arg2, arg3)
+                    };
+                    match ret {
+                        Err(SysError::EAGAIN) => {
+                            let tick = crate::consts::USEC_PER_TICK as u64;
+                            let tick = core::time::Duration::from_micros(tick);
+                            self.sleep_for(tick).await?;
+                        }
+                        ret => return ret,
+                    }
+                }
+            }
+            TIMERIOCDELAY => {
+                // the timer checks the delay, it is slept through without
+                // the process locked
+                let usecs = {
+                    let mut proc = self.process();
+                    proc.get_file_like(fd)?.ioctl(request, arg1, arg2, arg3)?
+                };
+                if usecs > 0 {
+                    let duration = core::time::Duration::from_micros(usecs as u64);
+                    self.sleep_for(duration).await?;
+                }
+                Ok(0)
+            }
             _ => {
                 let mut proc = self.process();
                 let file_like = proc.get_file_like(fd)?;
@@ -912,6 +1049,51 @@ impl Syscall<'_> {
         }
     }
 
+    /// Run a request whose argument points to a buffer. The device gets a
+    /// copy of the argument, with the buffer checked here: it copies
+    /// without locking the memory set, and sees the address and length
+    /// that were checked even if userspace changes them meanwhile.
+    fn ioctl_with_buf(
+        &mut self,
+        fd: usize,
+        request: usize,
+        arg1: usize,
+        arg_size: usize,
+        buf: crate::fs::ioctl::IoctlBuf,
+    ) -> SysResult {
+        use crate::fs::ioctl::IoctlBuf;
+        // aligned for the argument structures, large enough for any of them
+        let mut arg = [0usize; 4];
+        assert!(arg_size <= size_of::<[usize; 4]>());
+        let arg_ptr = arg.as_mut_ptr() as *mut u8;
+        unsafe { core::ptr::copy_nonoverlapping(arg1 as *const u8, arg_ptr, arg_size) };
+        let (addr, len) = (arg[0], arg[1]);
+        match buf {
+            IoctlBuf::In(elem_size) => unsafe {
+                let size = len.checked_mul(elem_size).ok_or(SysError::EINVAL)?;
+                self.vm()
+                    .check_read_array(addr as *const u8, size)
+                    .map_err(|_| FsError::IOCTLError)?;
+            },
+            IoctlBuf::Out(elem_size) => unsafe {
+                let size = len.checked_mul(elem_size).ok_or(SysError::EINVAL)?;
+                self.vm()
+                    .check_write_array(addr as *mut u8, size)
+                    .map_err(|_| FsError::IOCTLError)?;
+            },
+        }
+        let ret = {
+            let mut proc = self.process();
+            let file_like = proc.get_file_like(fd)?;
+            file_like.ioctl(request, arg_ptr as usize, 0, 0)?
+        };
+        if let IoctlBuf::Out(_) = buf {
+            // the device set the length of what it copied out
+            unsafe { core::ptr::copy_nonoverlapping(arg_ptr, arg1 as *mut u8, arg_size) };
+        }
+        Ok(ret)
+    }
+
     pub fn sys_chdir(&mut self, path: *const u8) -> SysResult {
         let mut proc = self.process();
         let path = check_and_clone_cstr(path)?;
@@ -1244,6 +1426,7 @@ impl Syscall<'_> {
             in_fd, out_fd, in_offset, out_offset, count, flags
         );
         let proc = self.process();
+        let eventbus = proc.eventbus.clone();
         // We know it's save, pacify the borrow checker
         let proc_cell = UnsafeCell::new(proc);
         let in_file = unsafe { (*proc_cell.get()).get_file(in_fd)? };
@@ -1273,7 +1456,9 @@ impl Syscall<'_> {
         let mut total_written = 0;
         while bytes_read < count {
             let len = min(buffer.len(), count - bytes_read);
-            let read_len = in_file.read_at(read_offset, &mut buffer[..len]).await?;
+            let read_len = in_file
+                .read_at(read_offset, &mut buffer[..len], &eventbus)
+                .await?;
             if read_len == 0 {
                 break;
             }
@@ -1283,7 +1468,15 @@ impl Syscall<'_> {
             let mut bytes_written = 0;
             let mut rlen = read_len;
             while bytes_written < read_len {
-                let write_len = out_file.write(&buffer[bytes_written..(bytes_written + rlen)])?;
+                let write_len = match out_file.write(&buffer[bytes_written..(bytes_written + rlen)])
+                {
+                    // a full pipe
+                    Err(FsError::Again) => {
+                        out_file.wait_writable(&eventbus).await?;
+                        continue;
+                    }
+                    ret => ret?,
+                };
                 if write_len == 0 {
                     info!(
                         "copy_file_range:END_ERR in: {}, out: {}, in_offset: {:?}, out_offset: {:?}, count: {} = bytes_read {}, bytes_written {}, write_len {}",
@@ -1393,6 +1586,28 @@ impl Process {
             "/proc/self/exe" => {
                 return Ok(Arc::new(Pseudo::new(&self.exec_path, FileType::SymLink)));
             }
+            "/proc/devices" => {
+                return Ok(Arc::new(Pseudo::new(&devices_report(), FileType::File)));
+            }
+            "/proc/cmdline" => {
+                let cmdline = format!("{}\n", *crate::drivers::CMDLINE.read());
+                return Ok(Arc::new(Pseudo::new(&cmdline, FileType::File)));
+            }
+            "/proc/meminfo" => {
+                let meminfo = crate::memory::meminfo_report();
+                return Ok(Arc::new(Pseudo::new(&meminfo, FileType::File)));
+            }
+            "/proc/uptime" => {
+                let uptime = crate::trap::uptime_report();
+                return Ok(Arc::new(Pseudo::new(&uptime, FileType::File)));
+            }
+            "/proc/interrupts" => {
+                let report = crate::trap::interrupts_report();
+                return Ok(Arc::new(Pseudo::new(&report, FileType::File)));
+            }
+            "/proc/tty/cc" => {
+                return Ok(Arc::new(Pseudo::new(&cc_report(), FileType::File)));
+            }
             _ => {}
         }
         let (fd_dir_path, fd_name) = split_path(&path);
@@ -1435,6 +1650,12 @@ fn split_path(path: &str) -> (&str, &str) {
     (dir_path, file_name)
 }
 
+/// The errno userspace sees for each file system error.
+///
+/// Devices should return an error instead of panicking on requests they
+/// do not handle: `NotSupported` for an operation the device lacks, like
+/// reading a write-only node, `InvalidParam` for a bad argument, and
+/// `IOCTLError` for an ioctl argument that can't be accessed.
 impl From<FsError> for SysError {
     fn from(error: FsError) -> Self {
         match error {
@@ -1452,7 +1673,7 @@ impl From<FsError> for SysError {
             FsError::WrongFs => SysError::EINVAL,
             FsError::DeviceError => SysError::EIO,
             FsError::IOCTLError => SysError::EINVAL,
-            FsError::NoDevice => SysError::EINVAL,
+            FsError::NoDevice => SysError::ENODEV,
             FsError::Again => SysError::EAGAIN,
             FsError::SymLoop => SysError::ELOOP,
             FsError::Busy => SysError::EBUSY,
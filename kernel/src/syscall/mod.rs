@@ -74,6 +74,8 @@ pub async fn handle_syscall(thread: &Arc<Thread>, context: &mut UserContext) ->
     };
     let ret = syscall.syscall(num, args).await;
     let exit = syscall.exit;
+    // console output printed meanwhile, maybe from interrupts
+    crate::logging::flush_log_file();
     context.set_syscall_ret(ret as usize);
     exit
 }
@@ -122,7 +124,7 @@ impl Syscall<'_> {
                 self.sys_read(args[0], UserOutPtr::from(args[1]), args[2])
                     .await
             }
-            SYS_WRITE => self.sys_write(args[0], args[1] as *const u8, args[2]),
+            SYS_WRITE => self.sys_write(args[0], args[1] as *const u8, args[2]).await,
             SYS_OPENAT => self.sys_openat(args[0], args[1] as *const u8, args[2], args[3]),
             SYS_CLOSE => self.sys_close(args[0]),
             SYS_FSTAT => self.sys_fstat(args[0], args[1] as *mut Stat),
@@ -130,7 +132,10 @@ impl Syscall<'_> {
                 self.sys_fstatat(args[0], args[1] as *const u8, args[2] as *mut Stat, args[3])
             }
             SYS_LSEEK => self.sys_lseek(args[0], args[1] as i64, args[2] as u8),
-            SYS_IOCTL => self.sys_ioctl(args[0], args[1], args[2], args[3], args[4]),
+            SYS_IOCTL => {
+                self.sys_ioctl(args[0], args[1], args[2], args[3], args[4])
+                    .await
+            }
             SYS_PREAD64 => {
                 self.sys_pread(args[0], UserOutPtr::from(args[1]), args[2], args[3])
                     .await
@@ -140,7 +145,10 @@ impl Syscall<'_> {
                 self.sys_readv(args[0], UserInPtr::from(args[1]), args[2])
                     .await
             }
-            SYS_WRITEV => self.sys_writev(args[0], args[1] as *const IoVec, args[2]),
+            SYS_WRITEV => {
+                self.sys_writev(args[0], args[1] as *const IoVec, args[2])
+                    .await
+            }
             SYS_SENDFILE => {
                 self.sys_sendfile(args[0], args[1], UserInOutPtr::from(args[2]), args[3])
                     .await
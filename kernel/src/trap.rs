@@ -3,6 +3,7 @@ use crate::consts::INFORM_PER_MSEC;
 use crate::process::*;
 use crate::sync::SpinNoIrqLock as Mutex;
 use crate::{signal::SignalUserContext, sync::Condvar};
+use alloc::string::String;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use core::time::Duration;
 use naive_timer::Timer;
@@ -31,6 +32,35 @@ pub fn uptime_msec() -> usize {
     unsafe { crate::trap::wall_tick() * crate::consts::USEC_PER_TICK / 1000 }
 }
 
+/// Text of /proc/uptime: seconds since boot and seconds the CPUs spent idle,
+/// with two decimals. Idle time is not tracked, it is always 0.
+pub fn uptime_report() -> String {
+    let msec = uptime_msec();
+    format!("{}.{:02} 0.00\n", msec / 1000, msec % 1000 / 10)
+}
+
+/// Text of /proc/interrupts: interrupts handled since boot, one `source:
+/// count` line per source. Timer interrupts are counted on all CPUs.
+/// Then how serial input is serviced, polled or by interrupt.
+pub fn interrupts_report() -> String {
+    use crate::drivers::{irq::handled_count, DeviceType};
+    let sources = [
+        ("uart", handled_count(DeviceType::Serial)),
+        ("net", handled_count(DeviceType::Net)),
+        ("block", handled_count(DeviceType::Block)),
+        ("input", handled_count(DeviceType::Input)),
+        ("gpu", handled_count(DeviceType::Gpu)),
+        ("rtc", handled_count(DeviceType::Rtc)),
+        ("intc", handled_count(DeviceType::Intc)),
+    ];
+    let mut report = format!("timer: {}\n", cpu_tick());
+    for (name, count) in sources.iter() {
+        report += &format!("{}: {}\n", name, count);
+    }
+    report += &crate::drivers::serial::polling_report();
+    report
+}
+
 lazy_static! {
     pub static ref NAIVE_TIMER: Mutex<Timer> = Mutex::new(Timer::default());
 }
@@ -41,13 +71,15 @@ pub fn timer() {
 
     let now = crate::arch::timer::timer_now();
     NAIVE_TIMER.lock().expire(now);
+
+    crate::drivers::serial::poll_input();
+    crate::fs::release_echo();
+    crate::fs::release_output();
 }
 
 pub fn serial(c: u8) {
-    if c == b'\r' {
-        // in linux, we use '\n' instead
-        crate::fs::TTY.push(b'\n');
-    } else {
-        crate::fs::TTY.push(c);
+    // interrupt handlers can not keep a byte the tty refused, it is lost
+    if !crate::fs::TTY.push(c) {
+        crate::fs::TTY.count_dropped(1);
     }
 }
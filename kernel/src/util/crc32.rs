@@ -0,0 +1,76 @@
+//! CRC-32 as used by zlib and Ethernet, computed incrementally
+//!
+//! The check value, the CRC of `b"123456789"`, is `0xCBF43926`.
+
+/// Reversed polynomial 0x04C11DB7
+const POLY: u32 = 0xEDB8_8320;
+
+lazy_static! {
+    static ref TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ POLY
+                } else {
+                    crc >> 1
+                };
+            }
+            *entry = crc;
+        }
+        table
+    };
+}
+
+/// CRC-32 of all bytes fed so far
+#[derive(Clone, Copy)]
+pub struct Crc32 {
+    /// inverted CRC, as the algorithm keeps it between bytes
+    state: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32 { state: !0 }
+    }
+}
+
+impl Crc32 {
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            let index = (self.state ^ b as u32) & 0xff;
+            self.state = (self.state >> 8) ^ TABLE[index as usize];
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        !self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Crc32;
+
+    #[test]
+    fn check_value() {
+        let mut crc = Crc32::default();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn incremental_update_matches_one_shot() {
+        let mut crc = Crc32::default();
+        crc.update(b"1234");
+        crc.update(b"");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(Crc32::default().value(), 0);
+    }
+}
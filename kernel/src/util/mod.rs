@@ -1,5 +1,8 @@
 use core::ptr::{read_volatile, write_volatile};
 
+pub mod crc32;
+pub mod text;
+
 /// Convert C string to Rust string
 pub unsafe fn from_cstr(s: *const u8) -> &'static str {
     use core::{slice, str};
@@ -0,0 +1,200 @@
+//! Render text reports with `core::fmt` into a bounded buffer, and put
+//! written text back together into lines
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A `fmt::Write` sink keeping at most `cap` bytes.
+///
+/// Output past the cap is dropped at a char boundary instead of failing,
+/// so a report is always rendered, possibly truncated.
+pub struct BoundedWriter {
+    buf: String,
+    cap: usize,
+    truncated: bool,
+}
+
+impl BoundedWriter {
+    pub fn new(cap: usize) -> Self {
+        BoundedWriter {
+            buf: String::new(),
+            cap,
+            truncated: false,
+        }
+    }
+
+    /// Whether some output was dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+
+    pub fn into_string(self) -> String {
+        self.buf
+    }
+}
+
+impl fmt::Write for BoundedWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.cap - self.buf.len();
+        if s.len() <= room {
+            self.buf.push_str(s);
+        } else {
+            let mut end = room;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.buf.push_str(&s[..end]);
+            self.truncated = true;
+        }
+        Ok(())
+    }
+}
+
+/// Append a `hexdump -C` style dump of `buf` to `out`, the first byte at
+/// `offset`: the offset, 16 bytes in hex, then the printable ones.
+pub fn hex_dump(offset: usize, buf: &[u8], out: &mut String) {
+    use core::fmt::Write;
+    for (i, row) in buf.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}", offset + i * 16);
+        for j in 0..16 {
+            if j % 8 == 0 {
+                out.push(' ');
+            }
+            match row.get(j) {
+                Some(c) => {
+                    let _ = write!(out, " {:02x}", c);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        for &c in row {
+            out.push(if (0x20..=0x7e).contains(&c) {
+                c as char
+            } else {
+                '.'
+            });
+        }
+        out.push_str("|\n");
+    }
+}
+
+/// Reassemble text written in arbitrary chunks into whole lines.
+///
+/// A line longer than `cap` bytes is emitted in pieces of `cap` bytes,
+/// so the held partial line never grows unbounded.
+pub struct LineAssembler {
+    partial: Vec<u8>,
+    cap: usize,
+}
+
+impl LineAssembler {
+    pub fn new(cap: usize) -> Self {
+        LineAssembler {
+            partial: Vec::new(),
+            cap,
+        }
+    }
+
+    /// Feed `buf`, call `emit` with each completed line without its newline.
+    /// The trailing partial line is held until the next call or `flush`.
+    pub fn push(&mut self, buf: &[u8], mut emit: impl FnMut(&[u8])) {
+        for &c in buf {
+            if c == b'\n' {
+                emit(&self.partial);
+                self.partial.clear();
+                continue;
+            }
+            self.partial.push(c);
+            if self.partial.len() >= self.cap {
+                emit(&self.partial);
+                self.partial.clear();
+            }
+        }
+    }
+
+    /// Emit the held partial line, if any.
+    pub fn flush(&mut self, mut emit: impl FnMut(&[u8])) {
+        if !self.partial.is_empty() {
+            emit(&self.partial);
+            self.partial.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn bounded_writer_formats() {
+        let mut writer = BoundedWriter::new(64);
+        write!(writer, "{}: {:#x} {:>4}", "irq", 0x2a, 7).unwrap();
+        assert_eq!(writer.as_str(), "irq: 0x2a    7");
+        assert!(!writer.is_truncated());
+    }
+
+    #[test]
+    fn bounded_writer_stops_at_cap() {
+        let mut writer = BoundedWriter::new(8);
+        write!(writer, "{}", "0123456789").unwrap();
+        assert_eq!(writer.as_str(), "01234567");
+        assert!(writer.is_truncated());
+        // later output is dropped too
+        write!(writer, "x").unwrap();
+        assert_eq!(writer.into_string(), "01234567");
+    }
+
+    #[test]
+    fn bounded_writer_cuts_at_char_boundary() {
+        let mut writer = BoundedWriter::new(4);
+        // 'é' takes two bytes, the second one would pass the cap
+        write!(writer, "abcé").unwrap();
+        assert_eq!(writer.as_str(), "abc");
+        assert!(writer.is_truncated());
+    }
+
+    #[test]
+    fn line_split_across_writes() {
+        let mut lines = Vec::new();
+        let mut assembler = LineAssembler::new(64);
+        assembler.push(b"hel", |line| lines.push(line.to_vec()));
+        assert!(lines.is_empty());
+        assembler.push(b"lo\nwor", |line| lines.push(line.to_vec()));
+        assert_eq!(lines, [b"hello".to_vec()]);
+        assembler.flush(|line| lines.push(line.to_vec()));
+        assert_eq!(lines, [b"hello".to_vec(), b"wor".to_vec()]);
+    }
+
+    #[test]
+    fn long_line_is_emitted_in_pieces() {
+        let mut lines = Vec::new();
+        let mut assembler = LineAssembler::new(4);
+        assembler.push(b"abcdef\n", |line| lines.push(line.to_vec()));
+        assert_eq!(lines, [b"abcd".to_vec(), b"ef".to_vec()]);
+    }
+
+    #[test]
+    fn hex_dump_matches_hexdump_c() {
+        let mut out = String::new();
+        hex_dump(0, b"Hello, world!\n\x00\x01abc", &mut out);
+        let expected = concat!(
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n",
+            "00000010  61 62 63                                          |abc|\n",
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn hex_dump_starts_at_offset() {
+        let mut out = String::new();
+        hex_dump(0x100, b"A", &mut out);
+        assert!(out.starts_with("00000100  41 "));
+    }
+}